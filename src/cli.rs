@@ -1,5 +1,9 @@
-use clap::Parser;
+use crate::db::DEFAULT_ROOT;
+use crate::models::TimestampSource;
+use chrono_tz::Tz;
+use clap::{Parser, ValueEnum};
 use serde::Deserialize;
+use std::collections::HashMap;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -15,24 +19,270 @@ pub struct Args {
 #[derive(Parser, Debug)]
 pub enum Commands {
     /// Import video files from a directory.
-    Import,
+    Import {
+        /// Reconcile the index against the disk after importing.
+        #[arg(long)]
+        reconcile: bool,
+        /// Walk every directory, ignoring the watermarks of unchanged ones.
+        #[arg(long)]
+        full: bool,
+        /// How to print the import report.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
     /// Run maintenance to enforce disk quota.
     Maintenance,
     /// Start the web viewer server.
-    Web,
+    Web {
+        /// Keep the index current by watching the video directory.
+        #[arg(long)]
+        watch: bool,
+    },
+    /// Watch the video directory and index recordings as they are added or removed.
+    Watch,
+    /// Re-derive start and end times of indexed videos after a timezone change.
+    Retime,
+    /// List indexed videos whose MP4 container is truncated or unreadable.
+    Health,
+    /// Mark indexed videos missing from disk as deleted and list unparsable files.
+    Reconcile,
+    /// Move recordings from an inbox directory into the archive layout and index them.
+    Ingest {
+        /// Directory the cameras upload into.
+        inbox: String,
+        /// Name of the root to file recordings into; defaults to the first root.
+        #[arg(long)]
+        root: Option<String>,
+    },
+    /// Move indexed recordings into a new directory layout.
+    Reorganize {
+        /// Target layout template, e.g. "{YYYY}/{MM}/{DD}/{camera}"; defaults to the
+        /// configured layout of each root.
+        #[arg(long)]
+        layout: Option<String>,
+        /// Only reorganize this root.
+        #[arg(long)]
+        root: Option<String>,
+        /// Print the planned moves without changing anything.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Manage the database.
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+}
+
+#[derive(Parser, Debug)]
+pub enum DbCommand {
+    /// Apply pending schema migrations.
+    Migrate {
+        /// List applied and pending migrations without applying any.
+        #[arg(long)]
+        status: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Json,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
+    /// Single archive directory, used when no `roots` are configured.
+    #[serde(default)]
     pub directory: String,
+    /// Named archive roots, e.g. one per storage volume.
+    #[serde(default)]
+    pub roots: Vec<RootConfig>,
     pub db_path: String,
+    /// Timezone the cameras' clocks are set to.
+    #[serde(default = "default_timezone")]
+    pub timezone: Tz,
+    /// Per-camera settings, keyed by the camera name shown in the index.
+    #[serde(default)]
+    pub cameras: HashMap<String, CameraConfig>,
+    #[serde(default)]
+    pub naming: NamingConfig,
+    /// Directory layout that `ingest` files recordings into, relative to their root.
+    #[serde(default = "default_layout")]
+    pub layout: String,
+    #[serde(default)]
+    pub scan: ScanConfig,
     pub maintenance: Option<MaintenanceConfig>,
     pub web_viewer: Option<WebViewerConfig>,
 }
 
+impl Config {
+    /// Returns the timezone for a camera, falling back to the global setting.
+    pub fn timezone_for(&self, camera_name: &str) -> Tz {
+        self.cameras
+            .get(self.camera_name_for(camera_name))
+            .and_then(|camera| camera.timezone)
+            .unwrap_or(self.timezone)
+    }
+
+    /// Returns the camera a name found in file names belongs to: the camera listing it in
+    /// `aliases`, or the name itself.
+    pub fn camera_name_for<'a>(&'a self, raw_name: &'a str) -> &'a str {
+        self.cameras
+            .iter()
+            .find(|(_, camera)| camera.aliases.iter().any(|alias| alias == raw_name))
+            .map(|(name, _)| name.as_str())
+            .unwrap_or(raw_name)
+    }
+
+    /// Returns the configured roots, or a single default root for `directory`.
+    pub fn roots(&self) -> Vec<RootConfig> {
+        if !self.roots.is_empty() {
+            return self.roots.clone();
+        }
+        vec![RootConfig {
+            name: DEFAULT_ROOT.to_string(),
+            path: self.directory.clone(),
+            naming: None,
+            layout: None,
+            quota: None,
+        }]
+    }
+}
+
+/// An archive root: a directory tree of recordings, typically one storage volume.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RootConfig {
+    pub name: String,
+    pub path: String,
+    /// Naming configuration for this root, overriding the top-level `naming`.
+    #[serde(default)]
+    pub naming: Option<NamingConfig>,
+    /// Directory layout for this root, overriding the top-level `layout`.
+    #[serde(default)]
+    pub layout: Option<String>,
+    /// Disk space quota for this root (e.g., "500GB"), enforced by maintenance.
+    #[serde(default)]
+    pub quota: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CameraConfig {
+    pub timezone: Option<Tz>,
+    /// Other names the camera appears under in file names, e.g. from before it was renamed.
+    /// Their recordings are indexed under this camera.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+/// Selects how recordings are recognised from their file names and layout.
+#[derive(Debug, Clone, Deserialize)]
+pub struct NamingConfig {
+    /// Scheme used for files that no `directories` entry matches.
+    #[serde(default = "default_scheme")]
+    pub scheme: String,
+    /// Per-directory scheme overrides, relative to the archive root.
+    #[serde(default)]
+    pub directories: Vec<DirectorySchemeConfig>,
+    /// User-defined regex schemes, referenced by name.
+    #[serde(default)]
+    pub patterns: Vec<PatternConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DirectorySchemeConfig {
+    pub path: String,
+    pub scheme: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PatternConfig {
+    pub name: String,
+    /// Matched against the path relative to `directory`, using `/` separators.
+    /// Must provide `camera`, `date` and `start` named captures. `end`, `offset`, `flags` (the
+    /// two-digit channel field of Reolink FTP names), `stream` and `trigger` are optional.
+    pub regex: String,
+    #[serde(default = "default_date_format")]
+    pub date_format: String,
+    #[serde(default = "default_time_format")]
+    pub time_format: String,
+}
+
 #[derive(Debug, Deserialize)]
+pub struct ScanConfig {
+    #[serde(default)]
+    pub dst_policy: DstPolicy,
+    /// Sources of recording times, tried in order until one succeeds.
+    #[serde(default = "default_timestamp_sources")]
+    pub timestamp_sources: Vec<TimestampSource>,
+    /// Truncated or unreadable recordings are moved here, keeping their relative path.
+    pub quarantine_dir: Option<String>,
+    /// Files modified more recently than this may still be being written.
+    #[serde(default = "default_min_age_seconds")]
+    pub min_age_seconds: u64,
+    /// How long to wait before re-checking recent files; 0 defers them to the next run.
+    #[serde(default)]
+    pub settle_seconds: u64,
+    /// Maximum number of files parsed and committed together.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// In watch mode, files that change without being closed are indexed after this long.
+    #[serde(default = "default_watch_quiet_seconds")]
+    pub watch_quiet_seconds: u64,
+    /// Glob patterns, relative to each root, of files and directories that are not walked.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Glob patterns, relative to each root, that files must match to be indexed; all files
+    /// are indexed when empty.
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// How many levels below each root are walked, counting files directly in the root as 1.
+    pub max_depth: Option<usize>,
+    /// Descend into symlinked directories. Links back to one of their own parent directories
+    /// are skipped.
+    #[serde(default)]
+    pub follow_symlinks: bool,
+    /// Maximum number of files parsed per second; unlimited if unset.
+    pub max_files_per_second: Option<u32>,
+    /// Maximum number of threads parsing files; one per CPU if unset.
+    pub max_parallelism: Option<usize>,
+    /// I/O scheduling priority of the threads reading the archive.
+    #[serde(default)]
+    pub io_priority: IoPriority,
+}
+
+/// I/O scheduling class for scan threads, so that camera writes take precedence. Only honoured
+/// on Linux, by I/O schedulers that support priorities (BFQ).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IoPriority {
+    /// Same priority as everything else.
+    #[default]
+    Normal,
+    /// Lowest level of the best-effort class.
+    Low,
+    /// Only use the disk when no one else does.
+    Idle,
+}
+
+/// How to resolve local times that are ambiguous (fall-back) or nonexistent (spring-forward).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DstPolicy {
+    /// Use the earlier of the candidate instants.
+    #[default]
+    Earliest,
+    /// Use the later of the candidate instants.
+    Latest,
+    /// Use the UTC offset encoded in the file name, falling back to `earliest`.
+    UseFilenameOffset,
+}
+
+#[derive(Debug, Default, Deserialize)]
 pub struct MaintenanceConfig {
-    pub quota: String,
+    /// Disk space quota across all roots.
+    #[serde(default)]
+    pub quota: Option<String>,
     #[serde(default)]
     pub dry_run: bool,
 }
@@ -50,6 +300,9 @@ pub struct WebViewerConfig {
     #[serde(default)]
     pub cache_size: String,
     pub features: Option<FeatureConfig>,
+    /// Database connections serving read requests concurrently; writes share a single one.
+    #[serde(default = "default_read_connections")]
+    pub read_connections: u32,
 }
 
 #[derive(Debug, Deserialize)]
@@ -83,6 +336,47 @@ pub struct FeatureConfig {
 }
 
 // Default value functions
+fn default_timezone() -> Tz {
+    chrono_tz::US::Eastern
+}
+
+fn default_scheme() -> String {
+    "auto".to_string()
+}
+
+fn default_timestamp_sources() -> Vec<TimestampSource> {
+    vec![
+        TimestampSource::BirthTime,
+        TimestampSource::Filename,
+        TimestampSource::Mtime,
+        TimestampSource::Mp4Header,
+    ]
+}
+
+fn default_min_age_seconds() -> u64 {
+    60
+}
+
+fn default_layout() -> String {
+    "{MM}{DD}{YYYY}".to_string()
+}
+
+fn default_batch_size() -> usize {
+    1000
+}
+
+fn default_watch_quiet_seconds() -> u64 {
+    10
+}
+
+fn default_date_format() -> String {
+    "%Y%m%d".to_string()
+}
+
+fn default_time_format() -> String {
+    "%H%M%S".to_string()
+}
+
 fn default_host() -> String {
     "0.0.0.0".to_string()
 }
@@ -91,6 +385,10 @@ fn default_port() -> u16 {
     8080
 }
 
+fn default_read_connections() -> u32 {
+    4
+}
+
 fn default_session_timeout() -> String {
     "24h".to_string()
 }
@@ -103,6 +401,37 @@ fn default_quality() -> String {
     "medium".to_string()
 }
 
+impl Default for NamingConfig {
+    fn default() -> Self {
+        Self {
+            scheme: default_scheme(),
+            directories: Vec::new(),
+            patterns: Vec::new(),
+        }
+    }
+}
+
+impl Default for ScanConfig {
+    fn default() -> Self {
+        Self {
+            dst_policy: DstPolicy::default(),
+            timestamp_sources: default_timestamp_sources(),
+            quarantine_dir: None,
+            min_age_seconds: default_min_age_seconds(),
+            settle_seconds: 0,
+            batch_size: default_batch_size(),
+            watch_quiet_seconds: default_watch_quiet_seconds(),
+            exclude: Vec::new(),
+            include: Vec::new(),
+            max_depth: None,
+            follow_symlinks: false,
+            max_files_per_second: None,
+            max_parallelism: None,
+            io_priority: IoPriority::default(),
+        }
+    }
+}
+
 impl Default for WebViewerConfig {
     fn default() -> Self {
         Self {
@@ -113,6 +442,7 @@ impl Default for WebViewerConfig {
             max_concurrent_streams: 10,
             cache_size: "1GB".to_string(),
             features: None,
+            read_connections: default_read_connections(),
         }
     }
 }
@@ -1,79 +1,149 @@
-use crate::models::VideoRecording;
-use chrono::{DateTime, Utc};
-use rusqlite::{Connection, Result};
-
-/// Initializes the database and creates the 'videos' table if it doesn't exist.
-pub fn init_db(conn: &Connection) -> Result<()> {
-    // First, check if we need to migrate the table
-    let mut has_old_schema = false;
-    let stmt = conn.prepare("PRAGMA table_info(videos)");
-    if let Ok(mut stmt) = stmt {
-        let rows = stmt.query_map([], |row| {
-            let column_name: String = row.get(1)?;
-            let column_type: String = row.get(2)?;
-            Ok((column_name, column_type))
-        });
-
-        if let Ok(rows) = rows {
-            for (name, type_name) in rows.flatten() {
-                if (name == "start_time" || name == "end_time") && type_name == "TEXT" {
-                    has_old_schema = true;
-                    break;
-                }
-            }
-        }
+use crate::models::{
+    MediaInfo, RecordingFlags, Stream, TimeFlag, TimestampSource, Trigger, VideoHealth,
+    VideoRecording,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::types::Value;
+use rusqlite::{
+    params_from_iter, Connection, OptionalExtension, Result, Row, Transaction, TransactionBehavior,
+};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Root name of rows indexed before archive roots were introduced, and of the root implied by
+/// a bare `directory` in the configuration.
+pub const DEFAULT_ROOT: &str = "default";
+
+/// Columns read by `row_to_recording`, in order.
+const RECORDING_COLUMNS: &str =
+    "file_path, camera_name, date, start_time, end_time, file_size, deleted,
+    time_flag, time_source, duration, width, height, codec, frame_rate, bitrate, health, file_mtime,
+    root, channel, stream, trigger, raw_camera_name, id";
+
+/// How long a connection waits for another to release its lock before giving up, e.g. while
+/// an import commits a batch.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Opens the database in WAL mode, so that readers are not blocked by a writer and a CLI
+/// import can run alongside the web server.
+pub fn open(path: &str) -> Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+    Ok(conn)
+}
+
+/// Starts a write transaction, taking the write lock up front. A deferred transaction that
+/// reads before writing cannot wait for the lock in WAL mode: if another connection commits in
+/// between, its first write fails at once with `SQLITE_BUSY_SNAPSHOT` instead of waiting out
+/// the busy timeout.
+pub fn begin_write(conn: &Connection) -> Result<Transaction<'_>> {
+    Transaction::new_unchecked(conn, TransactionBehavior::Immediate)
+}
+
+/// Pool of read-only connections, for serving queries concurrently with a single writer.
+pub type ReadPool = r2d2::Pool<ReadConnectionManager>;
+
+/// Opens a pool of `size` read-only connections to the database at `path`.
+pub fn open_read_pool(path: &str, size: u32) -> std::result::Result<ReadPool, r2d2::Error> {
+    r2d2::Pool::builder()
+        .max_size(size.max(1))
+        .build(ReadConnectionManager {
+            path: PathBuf::from(path),
+        })
+}
+
+/// Opens the connections of a `ReadPool`.
+#[derive(Debug)]
+pub struct ReadConnectionManager {
+    path: PathBuf,
+}
+
+impl r2d2::ManageConnection for ReadConnectionManager {
+    type Connection = Connection;
+    type Error = rusqlite::Error;
+
+    fn connect(&self) -> Result<Connection> {
+        let conn = Connection::open(&self.path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update(None, "query_only", true)?;
+        Ok(conn)
+    }
+
+    fn is_valid(&self, conn: &mut Connection) -> Result<()> {
+        conn.execute_batch("")
     }
 
-    if has_old_schema {
-        // Migrate existing table
-        conn.execute("ALTER TABLE videos RENAME TO videos_old", [])?;
-        conn.execute(
-            "CREATE TABLE videos (
-                file_path TEXT PRIMARY KEY,
-                camera_name TEXT NOT NULL,
-                date TEXT NOT NULL,
-                start_time DATETIME NOT NULL,
-                end_time DATETIME NOT NULL,
-                file_size INTEGER NOT NULL,
-                deleted BOOLEAN NOT NULL DEFAULT 0
-            )",
-            [],
-        )?;
-
-        // Migrate data from old table to new table
-        conn.execute(
-            "INSERT INTO videos (file_path, camera_name, date, start_time, end_time, file_size, deleted)
-             SELECT file_path, camera_name, date, datetime(start_time), datetime(end_time), file_size, deleted
-             FROM videos_old",
-            [],
-        )?;
-
-        // Drop the old table
-        conn.execute("DROP TABLE videos_old", [])?;
-    } else {
-        // Create new table with DATETIME columns
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS videos (
-                file_path TEXT PRIMARY KEY,
-                camera_name TEXT NOT NULL,
-                date TEXT NOT NULL,
-                start_time DATETIME NOT NULL,
-                end_time DATETIME NOT NULL,
-                file_size INTEGER NOT NULL,
-                deleted BOOLEAN NOT NULL DEFAULT 0
-            )",
-            [],
-        )?;
+    fn has_broken(&self, _conn: &mut Connection) -> bool {
+        false
     }
-    Ok(())
 }
 
-/// Inserts a single VideoRecording into the database, ignoring duplicates.
-pub fn insert_record(conn: &Connection, record: &VideoRecording) -> Result<usize> {
+/// What `upsert_record` did with a scanned recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpsertOutcome {
+    Added,
+    /// The file's size or modification time changed since it was indexed.
+    Updated,
+    /// The row was marked deleted but the file is back on disk.
+    Resurrected,
+    Unchanged,
+}
+
+/// Inserts a VideoRecording, or refreshes the existing row if the file changed or was
+/// previously marked deleted.
+pub fn upsert_record(conn: &Connection, record: &VideoRecording) -> Result<UpsertOutcome> {
+    let existing: Option<(u64, Option<DateTime<Utc>>, bool, String)> = conn
+        .query_row(
+            "SELECT file_size, file_mtime, deleted, root FROM videos WHERE file_path = ?1",
+            [&record.file_path],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .optional()?;
+
+    let outcome = match existing {
+        None => UpsertOutcome::Added,
+        Some((_, _, true, _)) => UpsertOutcome::Resurrected,
+        // Rows indexed before modification times were recorded are refreshed once, as are
+        // rows whose file now belongs to a differently named root
+        Some((size, mtime, false, root))
+            if size != record.file_size || mtime != record.file_mtime || root != record.root =>
+        {
+            UpsertOutcome::Updated
+        }
+        Some(_) => return Ok(UpsertOutcome::Unchanged),
+    };
+
     conn.execute(
-        "INSERT OR IGNORE INTO videos (file_path, camera_name, date, start_time, end_time, file_size, deleted)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-        (
+        "INSERT INTO videos (file_path, camera_name, date, start_time, end_time, file_size, deleted,
+                time_flag, time_source, duration, width, height, codec, frame_rate, bitrate, health,
+                file_mtime, root, channel, stream, trigger, raw_camera_name)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18,
+                 ?19, ?20, ?21, ?22)
+         ON CONFLICT(file_path) DO UPDATE SET
+            camera_name = excluded.camera_name,
+            date = excluded.date,
+            start_time = excluded.start_time,
+            end_time = excluded.end_time,
+            file_size = excluded.file_size,
+            deleted = excluded.deleted,
+            time_flag = excluded.time_flag,
+            time_source = excluded.time_source,
+            duration = excluded.duration,
+            width = excluded.width,
+            height = excluded.height,
+            codec = excluded.codec,
+            frame_rate = excluded.frame_rate,
+            bitrate = excluded.bitrate,
+            health = excluded.health,
+            file_mtime = excluded.file_mtime,
+            root = excluded.root,
+            channel = excluded.channel,
+            stream = excluded.stream,
+            trigger = excluded.trigger,
+            raw_camera_name = excluded.raw_camera_name",
+        rusqlite::params![
             &record.file_path,
             &record.camera_name,
             &record.date,
@@ -81,38 +151,284 @@ pub fn insert_record(conn: &Connection, record: &VideoRecording) -> Result<usize
             &record.end_time,
             &record.file_size,
             &record.deleted,
-        ),
+            record.time_flag.map(|flag| flag.as_str()),
+            record.time_source.as_str(),
+            &record.media.duration,
+            &record.media.width,
+            &record.media.height,
+            &record.media.codec,
+            &record.media.frame_rate,
+            &record.media.bitrate,
+            record.health.as_str(),
+            &record.file_mtime,
+            &record.root,
+            &record.flags.channel,
+            record.flags.stream.map(|stream| stream.as_str()),
+            record.flags.trigger.map(|trigger| trigger.as_str()),
+            &record.raw_camera_name,
+        ],
+    )?;
+    Ok(outcome)
+}
+
+/// Which videos `query_videos` returns. Unset fields match every video.
+#[derive(Debug, Clone, Default)]
+pub struct VideoFilter {
+    /// Videos from any of these cameras, by configured name.
+    pub cameras: Vec<String>,
+    /// Videos under any of these archive roots.
+    pub roots: Vec<String>,
+    /// Start of the recording time range, inclusive.
+    pub from: Option<TimeBound>,
+    /// End of the recording time range, exclusive.
+    pub to: Option<TimeBound>,
+    /// Videos recorded for any of these triggers.
+    pub triggers: Vec<Trigger>,
+    pub health: Option<HealthFilter>,
+    /// File size bounds in bytes, inclusive.
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// Duration bounds in seconds, inclusive. Videos of unknown duration never match them.
+    pub min_duration: Option<f64>,
+    pub max_duration: Option<f64>,
+    pub deleted: Option<bool>,
+    /// Text contained in the camera name or file name. The directories are not searched, so
+    /// that API clients cannot probe server paths.
+    pub text: Option<String>,
+    pub sort: VideoSort,
+    pub limit: Option<u32>,
+    pub offset: u32,
+}
+
+/// One end of a recording time range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeBound {
+    /// Compared with the recording date, which is in the camera's local time.
+    Date(NaiveDate),
+    /// Compared with the start time.
+    Instant(DateTime<Utc>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthFilter {
+    Is(VideoHealth),
+    /// Any health other than ok.
+    Unhealthy,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VideoSort {
+    #[default]
+    Newest,
+    Oldest,
+}
+
+impl VideoFilter {
+    /// Every video still on disk, oldest first.
+    pub fn on_disk() -> Self {
+        Self {
+            deleted: Some(false),
+            sort: VideoSort::Oldest,
+            ..Default::default()
+        }
+    }
+
+    /// Builds the WHERE clause and its parameters.
+    fn where_clause(&self) -> (String, Vec<Value>) {
+        let mut clause = String::from("WHERE 1=1");
+        let mut params = Vec::new();
+
+        push_any(&mut clause, &mut params, "camera_name", &self.cameras);
+        push_any(&mut clause, &mut params, "root", &self.roots);
+        let triggers: Vec<&str> = self.triggers.iter().map(|t| t.as_str()).collect();
+        push_any(&mut clause, &mut params, "trigger", &triggers);
+
+        for (bound, operator) in [(self.from, ">="), (self.to, "<")] {
+            match bound {
+                Some(TimeBound::Date(date)) => {
+                    clause.push_str(&format!(" AND date {} ?", operator));
+                    params.push(Value::Text(date.format("%Y-%m-%d").to_string()));
+                }
+                Some(TimeBound::Instant(instant)) => {
+                    // Start times are stored as text, which compares in time order
+                    clause.push_str(&format!(" AND start_time {} ?", operator));
+                    params.push(Value::Text(instant.format("%Y-%m-%d %H:%M:%S").to_string()));
+                }
+                None => {}
+            }
+        }
+        match self.health {
+            Some(HealthFilter::Is(health)) => {
+                clause.push_str(" AND health = ?");
+                params.push(Value::Text(health.as_str().to_string()));
+            }
+            Some(HealthFilter::Unhealthy) => clause.push_str(" AND health != 'ok'"),
+            None => {}
+        }
+        for (bound, operator) in [(self.min_size, ">="), (self.max_size, "<=")] {
+            if let Some(size) = bound {
+                clause.push_str(&format!(" AND file_size {} ?", operator));
+                params.push(Value::Integer(i64::try_from(size).unwrap_or(i64::MAX)));
+            }
+        }
+        for (bound, operator) in [(self.min_duration, ">="), (self.max_duration, "<=")] {
+            if let Some(duration) = bound {
+                clause.push_str(&format!(" AND duration {} ?", operator));
+                params.push(Value::Real(duration));
+            }
+        }
+        if let Some(deleted) = self.deleted {
+            clause.push_str(" AND deleted = ?");
+            params.push(Value::Integer(deleted.into()));
+        }
+        if let Some(text) = &self.text {
+            // Wildcards in the text match literally
+            let escaped = text
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            let pattern = format!("%{}%", escaped);
+            // Trimming every character but '/' from the end leaves the directory, so the file
+            // name is what follows it
+            clause.push_str(
+                " AND (camera_name LIKE ? ESCAPE '\\'
+                  OR substr(file_path, length(rtrim(file_path, replace(file_path, '/', ''))) + 1)
+                     LIKE ? ESCAPE '\\')",
+            );
+            params.push(Value::Text(pattern.clone()));
+            params.push(Value::Text(pattern));
+        }
+        (clause, params)
+    }
+}
+
+/// Appends a condition matching `column` against any of `values`; none when empty.
+fn push_any(
+    clause: &mut String,
+    params: &mut Vec<Value>,
+    column: &str,
+    values: &[impl AsRef<str>],
+) {
+    if values.is_empty() {
+        return;
+    }
+    let placeholders = vec!["?"; values.len()].join(", ");
+    clause.push_str(&format!(" AND {} IN ({})", column, placeholders));
+    params.extend(values.iter().map(|v| Value::Text(v.as_ref().to_string())));
+}
+
+/// Retrieves the video recordings matching `filter`, sorted and paginated as it asks.
+pub fn query_videos(conn: &Connection, filter: &VideoFilter) -> Result<Vec<VideoRecording>> {
+    let (clause, mut params) = filter.where_clause();
+    let order = match filter.sort {
+        VideoSort::Newest => "start_time DESC, id DESC",
+        VideoSort::Oldest => "start_time, id",
+    };
+    let mut sql = format!(
+        "SELECT {} FROM videos {} ORDER BY {}",
+        RECORDING_COLUMNS, clause, order
+    );
+    // SQLite only accepts OFFSET after a LIMIT, where -1 means none
+    if filter.limit.is_some() || filter.offset > 0 {
+        sql.push_str(" LIMIT ? OFFSET ?");
+        params.push(Value::Integer(filter.limit.map_or(-1, i64::from)));
+        params.push(Value::Integer(filter.offset.into()));
+    }
+
+    let mut stmt = conn.prepare(&sql)?;
+    let records = stmt.query_map(params_from_iter(params), row_to_recording)?;
+    records.collect()
+}
+
+/// Counts the video recordings matching `filter`, ignoring its pagination.
+pub fn count_videos(conn: &Connection, filter: &VideoFilter) -> Result<u64> {
+    let (clause, params) = filter.where_clause();
+    conn.query_row(
+        &format!("SELECT COUNT(*) FROM videos {}", clause),
+        params_from_iter(params),
+        |row| row.get(0),
     )
 }
 
-/// Retrieves all non-deleted video recordings, sorted by date and start time.
-pub fn get_all_non_deleted_recordings(conn: &Connection) -> Result<Vec<VideoRecording>> {
-    let mut stmt = conn.prepare(
-        "SELECT file_path, camera_name, date, start_time, end_time, file_size, deleted
-         FROM videos
-         WHERE deleted = 0
-         ORDER BY date, start_time",
-    )?;
-    let record_iter = stmt.query_map([], |row| {
-        let start_time: DateTime<Utc> = row.get(3)?;
-        let end_time: DateTime<Utc> = row.get(4)?;
-
-        Ok(VideoRecording {
-            file_path: row.get(0)?,
-            camera_name: row.get(1)?,
-            date: row.get(2)?,
-            start_time,
-            end_time,
-            file_size: row.get(5)?,
-            deleted: row.get(6)?,
+/// Number of matching videos and start of the latest one, for one camera.
+#[derive(Debug)]
+pub struct CameraSummary {
+    pub name: String,
+    pub video_count: u64,
+    pub last_recording: Option<DateTime<Utc>>,
+}
+
+/// Summarizes the video recordings matching `filter` per camera, sorted by camera name.
+pub fn summarize_cameras(conn: &Connection, filter: &VideoFilter) -> Result<Vec<CameraSummary>> {
+    let (clause, params) = filter.where_clause();
+    let mut stmt = conn.prepare(&format!(
+        "SELECT camera_name, COUNT(*), MAX(start_time) FROM videos {}
+         GROUP BY camera_name ORDER BY camera_name",
+        clause
+    ))?;
+    let cameras = stmt.query_map(params_from_iter(params), |row| {
+        Ok(CameraSummary {
+            name: row.get(0)?,
+            video_count: row.get(1)?,
+            last_recording: row.get(2)?,
         })
     })?;
+    cameras.collect()
+}
 
-    let mut records = Vec::new();
-    for record in record_iter {
-        records.push(record?);
-    }
-    Ok(records)
+/// Retrieves a single video recording by its ID.
+pub fn get_recording(conn: &Connection, id: i64) -> Result<VideoRecording> {
+    conn.query_row(
+        &format!("SELECT {} FROM videos WHERE id = ?1", RECORDING_COLUMNS),
+        [id],
+        row_to_recording,
+    )
+}
+
+/// Maps a row selected with `RECORDING_COLUMNS` to a VideoRecording.
+pub fn row_to_recording(row: &Row) -> Result<VideoRecording> {
+    let start_time: DateTime<Utc> = row.get(3)?;
+    let end_time: DateTime<Utc> = row.get(4)?;
+
+    Ok(VideoRecording {
+        file_path: row.get(0)?,
+        camera_name: row.get(1)?,
+        date: row.get(2)?,
+        start_time,
+        end_time,
+        file_size: row.get(5)?,
+        deleted: row.get(6)?,
+        time_flag: row
+            .get::<_, Option<String>>(7)?
+            .as_deref()
+            .and_then(TimeFlag::parse),
+        time_source: TimestampSource::parse(&row.get::<_, String>(8)?)
+            .unwrap_or(TimestampSource::BirthTime),
+        media: MediaInfo {
+            duration: row.get(9)?,
+            width: row.get(10)?,
+            height: row.get(11)?,
+            codec: row.get(12)?,
+            frame_rate: row.get(13)?,
+            bitrate: row.get(14)?,
+        },
+        health: VideoHealth::parse(&row.get::<_, String>(15)?).unwrap_or(VideoHealth::Ok),
+        file_mtime: row.get(16)?,
+        root: row.get(17)?,
+        raw_camera_name: row.get(21)?,
+        id: row.get(22)?,
+        flags: RecordingFlags {
+            channel: row.get(18)?,
+            stream: row
+                .get::<_, Option<String>>(19)?
+                .as_deref()
+                .and_then(Stream::parse),
+            trigger: row
+                .get::<_, Option<String>>(20)?
+                .as_deref()
+                .and_then(Trigger::parse),
+        },
+    })
 }
 
 /// Marks a video recording as deleted in the database.
@@ -122,3 +438,192 @@ pub fn mark_as_deleted(conn: &Connection, file_path: &str) -> Result<usize> {
         [file_path],
     )
 }
+
+/// Returns the distinct camera names found in the file names of indexed videos.
+pub fn get_raw_camera_names(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn
+        .prepare("SELECT DISTINCT raw_camera_name FROM videos WHERE raw_camera_name IS NOT NULL")?;
+    let names = stmt.query_map([], |row| row.get(0))?;
+    names.collect()
+}
+
+/// Sets the camera of every video whose file names use `raw_name` to `camera_name`.
+pub fn rename_camera(conn: &Connection, raw_name: &str, camera_name: &str) -> Result<usize> {
+    conn.execute(
+        "UPDATE videos SET camera_name = ?1 WHERE raw_camera_name = ?2 AND camera_name != ?1",
+        rusqlite::params![camera_name, raw_name],
+    )
+}
+
+/// Marks every indexed video under `directory` as deleted.
+pub fn mark_directory_deleted(conn: &Connection, directory: &str) -> Result<usize> {
+    let prefix = format!("{}/", directory.trim_end_matches('/'));
+    conn.execute(
+        "UPDATE videos SET deleted = 1
+         WHERE deleted = 0 AND substr(file_path, 1, length(?1)) = ?1",
+        [prefix],
+    )
+}
+
+/// Updates the recording times of a video, where they were derived from, and the flags
+/// parsed from its file name.
+pub fn update_times(conn: &Connection, record: &VideoRecording) -> Result<usize> {
+    conn.execute(
+        "UPDATE videos SET start_time = ?1, end_time = ?2, time_flag = ?3, time_source = ?4,
+            channel = ?5, stream = ?6, trigger = ?7
+         WHERE file_path = ?8",
+        rusqlite::params![
+            &record.start_time,
+            &record.end_time,
+            record.time_flag.map(|flag| flag.as_str()),
+            record.time_source.as_str(),
+            &record.flags.channel,
+            record.flags.stream.map(|stream| stream.as_str()),
+            record.flags.trigger.map(|trigger| trigger.as_str()),
+            &record.file_path,
+        ],
+    )
+}
+
+/// Returns the last directory committed by an interrupted scan of `root`.
+pub fn get_scan_checkpoint(conn: &Connection, root: &str) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT directory FROM scan_checkpoints WHERE root = ?1",
+        [root],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Records that every directory of `root` up to `directory`, in walk order, has been committed.
+pub fn set_scan_checkpoint(conn: &Connection, root: &str, directory: &str) -> Result<usize> {
+    conn.execute(
+        "INSERT INTO scan_checkpoints (root, directory) VALUES (?1, ?2)
+         ON CONFLICT(root) DO UPDATE SET directory = excluded.directory",
+        [root, directory],
+    )
+}
+
+/// Removes the checkpoint of `root` once a scan has walked it completely.
+pub fn clear_scan_checkpoint(conn: &Connection, root: &str) -> Result<usize> {
+    conn.execute("DELETE FROM scan_checkpoints WHERE root = ?1", [root])
+}
+
+/// Modification time and video count of a directory when it was last fully indexed.
+#[derive(Debug, Clone, Copy)]
+pub struct DirectoryWatermark {
+    pub mtime: DateTime<Utc>,
+    pub file_count: usize,
+}
+
+/// Returns the stored watermarks, keyed by directory path.
+pub fn get_directory_watermarks(conn: &Connection) -> Result<HashMap<String, DirectoryWatermark>> {
+    let mut stmt = conn.prepare("SELECT directory, mtime, file_count FROM directory_watermarks")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get(0)?,
+            DirectoryWatermark {
+                mtime: row.get(1)?,
+                file_count: row.get(2)?,
+            },
+        ))
+    })?;
+    rows.collect()
+}
+
+pub fn set_directory_watermark(
+    conn: &Connection,
+    directory: &str,
+    watermark: &DirectoryWatermark,
+) -> Result<usize> {
+    conn.execute(
+        "INSERT INTO directory_watermarks (directory, mtime, file_count) VALUES (?1, ?2, ?3)
+         ON CONFLICT(directory) DO UPDATE SET
+            mtime = excluded.mtime,
+            file_count = excluded.file_count",
+        rusqlite::params![directory, watermark.mtime, watermark.file_count],
+    )
+}
+
+/// Points a video at its new location, replacing any deleted row already recorded there.
+pub fn rename_video(conn: &Connection, old_path: &str, new_path: &str) -> Result<usize> {
+    conn.execute(
+        "DELETE FROM videos WHERE file_path = ?1 AND deleted = 1",
+        [new_path],
+    )?;
+    conn.execute(
+        "UPDATE videos SET file_path = ?2 WHERE file_path = ?1",
+        [old_path, new_path],
+    )
+}
+
+/// Returns the moves recorded by a reorganization that has not finished, as (old, new) paths.
+pub fn get_reorganize_journal(conn: &Connection) -> Result<Vec<(String, String)>> {
+    let mut stmt =
+        conn.prepare("SELECT old_path, new_path FROM reorganize_journal ORDER BY old_path")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+
+/// Records a move before it is carried out.
+pub fn add_journal_entry(conn: &Connection, old_path: &str, new_path: &str) -> Result<usize> {
+    conn.execute(
+        "INSERT OR REPLACE INTO reorganize_journal (old_path, new_path) VALUES (?1, ?2)",
+        [old_path, new_path],
+    )
+}
+
+pub fn remove_journal_entry(conn: &Connection, old_path: &str) -> Result<usize> {
+    conn.execute(
+        "DELETE FROM reorganize_journal WHERE old_path = ?1",
+        [old_path],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations;
+
+    #[test]
+    fn text_search_matches_file_names_only() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&conn).unwrap();
+        for (camera, path) in [
+            (
+                "Garage",
+                "/mnt/reolink/10182024/Garage-00-120000-120500.mp4",
+            ),
+            (
+                "Porch",
+                "/mnt/reolink/10182024/Porch_50%-00-130000-130500.mp4",
+            ),
+        ] {
+            conn.execute(
+                "INSERT INTO videos (file_path, camera_name, raw_camera_name, date, start_time,
+                 end_time, file_size) VALUES (?1, ?2, ?2, '2024-10-18', ?3, ?3, 0)",
+                rusqlite::params![path, camera, Utc::now()],
+            )
+            .unwrap();
+        }
+
+        let search = |text: &str| {
+            let filter = VideoFilter {
+                text: Some(text.to_string()),
+                ..Default::default()
+            };
+            query_videos(&conn, &filter)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.camera_name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(search("garage"), ["Garage"]);
+        assert_eq!(search("-00-13"), ["Porch"]);
+        assert!(search("/mnt/reolink").is_empty());
+        assert!(search("10182024").is_empty());
+        assert_eq!(search("_50%"), ["Porch"]);
+        assert_eq!(search("%"), ["Porch"]);
+        assert!(search("e_0").is_empty());
+    }
+}
@@ -0,0 +1,287 @@
+use crate::cli::Config;
+use crate::db;
+use crate::naming::ParsedName;
+use crate::scanner::{self, Indexer, Root, ScanReport};
+use crate::walk::WalkRules;
+use chrono::{DateTime, Utc};
+use rusqlite::Connection;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// Moves recordings from `inbox` into the layout of a root and indexes them. Files whose target
+/// is already taken are left in the inbox and reported as duplicates, when the contents are
+/// identical, or as conflicts.
+pub fn run_ingest(
+    config: &Config,
+    conn: &Connection,
+    inbox: &Path,
+    root_name: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let roots = scanner::load_roots(config)?;
+    let root = match root_name {
+        Some(name) => roots
+            .iter()
+            .find(|root| root.name == name)
+            .ok_or_else(|| format!("Unknown root: {}", name))?,
+        None => roots.first().ok_or("No archive roots configured")?,
+    };
+
+    let (files, deferred) = scanner::split_unstable(
+        WalkRules::new(&config.scan)?.find_video_files(inbox, inbox),
+        &config.scan,
+    );
+    for path in &deferred {
+        println!("Deferred: {} (may still be uploading)", path.display());
+    }
+
+    let mut planned = Vec::new();
+    let mut unrecognized = 0;
+    for source in &files {
+        match target_path(source, inbox, root, config) {
+            Some(target) => planned.push((source.clone(), target)),
+            None => {
+                println!("Unrecognized: {}", source.display());
+                unrecognized += 1;
+            }
+        }
+    }
+
+    // Files are read before they move, since a copy to another volume gets new file times
+    let indexer = Indexer::new(&roots, config)?;
+    let parsed = indexer.parse_moves(&planned);
+
+    let mut moved = Vec::new();
+    let (mut duplicates, mut conflicts, mut failed) = (0, 0, 0);
+    for ((source, target), parsed) in planned.iter().zip(parsed) {
+        match move_file(source, target) {
+            Ok(()) => {
+                println!("Ingested: {} -> {}", source.display(), target.display());
+                moved.push(parsed);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                if same_contents(source, target).unwrap_or(false) {
+                    println!(
+                        "Duplicate: {} (same as {})",
+                        source.display(),
+                        target.display()
+                    );
+                    duplicates += 1;
+                } else {
+                    println!(
+                        "Conflict: {} ({} exists with different contents)",
+                        source.display(),
+                        target.display()
+                    );
+                    conflicts += 1;
+                }
+            }
+            Err(e) => {
+                println!("Failed: {} ({})", source.display(), e);
+                failed += 1;
+            }
+        }
+    }
+
+    let ingested = moved.len();
+    let mut report = ScanReport::default();
+    let tx = db::begin_write(conn)?;
+    indexer.store_files(moved, &tx, &mut report)?;
+    tx.commit()?;
+    for skipped in &report.skipped {
+        println!("Not indexed: {}: {}", skipped.path, skipped.reason);
+    }
+    for quarantined in &report.quarantined {
+        println!("Quarantined: {} -> {}", quarantined.from, quarantined.to);
+    }
+    for error in &report.errors {
+        println!("Error: {}", error);
+    }
+
+    println!(
+        "{} ingested ({} added to the index), {} duplicates, {} conflicts, {} unrecognized, {} deferred, {} failed.",
+        ingested,
+        report.added + report.updated + report.resurrected,
+        duplicates,
+        conflicts,
+        unrecognized,
+        deferred.len(),
+        failed
+    );
+    Ok(())
+}
+
+/// Works out where an inbox file belongs in `root`, provided the root's naming schemes will
+/// recognise it there.
+fn target_path(source: &Path, inbox: &Path, root: &Root, config: &Config) -> Option<PathBuf> {
+    let parsed = parse_inbox_file(source, inbox, root, config)?;
+    let relative = root
+        .layout
+        .directory_for(&parsed.camera_name, parsed.date)
+        .join(source.file_name()?);
+    root.schemes.parse(&relative)?;
+    Some(root.path.join(relative))
+}
+
+/// Recognises an inbox file with the naming schemes of `root`. Names that rely on a date
+/// directory, like `reolink_ftp` uploads, are tried as if they were in the directory for the
+/// local date the file was uploaded.
+fn parse_inbox_file(path: &Path, inbox: &Path, root: &Root, config: &Config) -> Option<ParsedName> {
+    let relative = path.strip_prefix(inbox).ok()?;
+    if let Some(parsed) = root.schemes.parse(relative) {
+        return Some(parsed);
+    }
+
+    let modified: DateTime<Utc> = fs::metadata(path).ok()?.modified().ok()?.into();
+    let date = modified.with_timezone(&config.timezone).date_naive();
+    let dated = Path::new(&date.format("%m%d%Y").to_string()).join(path.file_name()?);
+    let mut parsed = root.schemes.parse(&dated)?;
+
+    // Uploads finish after the recording ends, which is a day after its start when it spans midnight
+    if parsed.end.is_some_and(|end| end < parsed.start) {
+        parsed.date = parsed.date.pred_opt()?;
+    }
+    Some(parsed)
+}
+
+/// Moves `source` to `target` without replacing an existing file, failing with `AlreadyExists`
+/// if the target is taken. The target never appears partially written, even when moving
+/// across filesystems.
+pub fn move_file(source: &Path, target: &Path) -> io::Result<()> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    match rename_exclusive(source, target) {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => {}
+        result => return result,
+    }
+
+    // On another filesystem: copy next to the target, then move it into place
+    let file_name = target.file_name().unwrap_or_default().to_string_lossy();
+    let partial = target.with_file_name(format!(".{}.partial", file_name));
+    let copied = fs::copy(source, &partial)
+        .and_then(|_| {
+            // Keep the modification time, which may be the recording's only timestamp
+            let file = File::options().write(true).open(&partial)?;
+            file.set_modified(fs::metadata(source)?.modified()?)?;
+            file.sync_all()
+        })
+        .and_then(|()| rename_exclusive(&partial, target));
+    if let Err(e) = copied {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+    fs::remove_file(source)
+}
+
+/// Renames `source` to `target` on the same filesystem, failing with `AlreadyExists` instead of
+/// replacing a target that exists, even one created concurrently.
+fn rename_exclusive(source: &Path, target: &Path) -> io::Result<()> {
+    // Linking fails if the target exists, so nothing is ever overwritten
+    let link_error = match fs::hard_link(source, target) {
+        Ok(()) => return fs::remove_file(source),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Err(e),
+        Err(e) => e,
+    };
+    // Hard links are unavailable across filesystems and on some (e.g. exFAT)
+    rename_no_replace(source, target).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => e,
+        _ => link_error,
+    })
+}
+
+/// Renames with `RENAME_NOREPLACE`, which the kernel checks atomically.
+#[cfg(target_os = "linux")]
+fn rename_no_replace(source: &Path, target: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let source = CString::new(source.as_os_str().as_bytes())?;
+    let target = CString::new(target.as_os_str().as_bytes())?;
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            libc::AT_FDCWD,
+            source.as_ptr(),
+            libc::AT_FDCWD,
+            target.as_ptr(),
+            libc::RENAME_NOREPLACE,
+        )
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn rename_no_replace(_source: &Path, _target: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "renaming without replacing needs hard links on this platform",
+    ))
+}
+
+/// Compares two files byte for byte.
+pub fn same_contents(a: &Path, b: &Path) -> io::Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+
+    let mut a = BufReader::new(File::open(a)?);
+    let mut b = BufReader::new(File::open(b)?);
+    let mut buf_a = [0u8; 8192];
+    let mut buf_b = [0u8; 8192];
+    loop {
+        let read = a.read(&mut buf_a)?;
+        if read == 0 {
+            return Ok(true);
+        }
+        b.read_exact(&mut buf_b[..read])?;
+        if buf_a[..read] != buf_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("reopal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn move_file_moves_into_new_directories() {
+        let dir = scratch_dir("move");
+        let source = dir.join("upload.mp4");
+        let target = dir.join("2024/01/31/upload.mp4");
+        fs::write(&source, b"video").unwrap();
+
+        move_file(&source, &target).unwrap();
+        assert!(!source.exists());
+        assert_eq!(fs::read(&target).unwrap(), b"video");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn move_file_never_replaces_target() {
+        let dir = scratch_dir("no-replace");
+        let source = dir.join("upload.mp4");
+        let target = dir.join("archived.mp4");
+        fs::write(&source, b"new").unwrap();
+        fs::write(&target, b"old").unwrap();
+
+        let error = move_file(&source, &target).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&source).unwrap(), b"new");
+        assert_eq!(fs::read(&target).unwrap(), b"old");
+        assert!(!dir.join(".archived.mp4.partial").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
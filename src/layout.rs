@@ -0,0 +1,49 @@
+use chrono::{Datelike, NaiveDate};
+use std::path::PathBuf;
+
+/// Placeholders a layout template may use.
+const PLACEHOLDERS: [&str; 4] = ["camera", "YYYY", "MM", "DD"];
+
+/// A directory layout for an archive root, such as `{MM}{DD}{YYYY}` or
+/// `{YYYY}/{MM}/{DD}/{camera}`. Recordings keep their file name and are placed in the directory
+/// the template renders to, relative to the root.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    template: String,
+}
+
+impl Layout {
+    pub fn parse(template: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut rest = template;
+        while let Some(open) = rest.find('{') {
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| format!("Unclosed placeholder in layout: {}", template))?
+                + open;
+            let name = &rest[open + 1..close];
+            if !PLACEHOLDERS.contains(&name) {
+                return Err(
+                    format!("Unknown placeholder {{{}}} in layout: {}", name, template).into(),
+                );
+            }
+            rest = &rest[close + 1..];
+        }
+
+        Ok(Self {
+            template: template.to_string(),
+        })
+    }
+
+    /// Directory, relative to the root, that a recording from `camera` on `date` belongs in.
+    pub fn directory_for(&self, camera: &str, date: NaiveDate) -> PathBuf {
+        // Keep camera names from introducing extra directory levels
+        let camera = camera.replace(['/', '\\'], "_");
+        PathBuf::from(
+            self.template
+                .replace("{camera}", &camera)
+                .replace("{YYYY}", &format!("{:04}", date.year()))
+                .replace("{MM}", &format!("{:02}", date.month()))
+                .replace("{DD}", &format!("{:02}", date.day())),
+        )
+    }
+}
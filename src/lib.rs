@@ -1,6 +1,17 @@
 pub mod cli;
 pub mod db;
+pub mod ingest;
+pub mod layout;
 pub mod maintenance;
+pub mod migrations;
 pub mod models;
+pub mod mp4;
+pub mod naming;
+pub mod reconcile;
+pub mod reorganize;
 pub mod scanner;
+pub mod throttle;
+pub mod timestamps;
+pub mod walk;
+pub mod watcher;
 pub mod web;
@@ -1,11 +1,20 @@
 use clap::Parser;
-use reopal::cli::{Args, Commands, Config};
-use reopal::db;
+use reopal::cli::{Args, Commands, Config, DbCommand, OutputFormat};
+use reopal::db::{self, HealthFilter, VideoFilter};
+use reopal::ingest;
 use reopal::maintenance;
-use reopal::scanner;
+use reopal::migrations;
+use reopal::reconcile::{self, ReconcileReport};
+use reopal::reorganize;
+use reopal::scanner::{self, ScanReport};
+use reopal::watcher;
 use reopal::web::{AppState, WebServer};
 use rusqlite::{Connection, Result};
+use serde::Serialize;
 use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -13,36 +22,175 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let config_str = fs::read_to_string(&args.config)?;
     let config: Config = serde_yaml::from_str(&config_str)?;
 
-    let conn = Connection::open(&config.db_path)?;
-    db::init_db(&conn)?;
+    let conn = db::open(&config.db_path)?;
+    // Report the schema before migrating it, which every other command does first
+    if let Commands::Db {
+        command: DbCommand::Migrate { status: true },
+    } = args.command
+    {
+        print_migration_status(&conn)?;
+        return Ok(());
+    }
+    for migration in migrations::migrate(&conn)? {
+        eprintln!(
+            "Applied schema migration {}: {}",
+            migration.version, migration.description
+        );
+    }
+    let renamed = scanner::apply_camera_aliases(&config, &conn)?;
+    if renamed > 0 {
+        eprintln!("Applied camera aliases to {} indexed videos", renamed);
+    }
 
     match args.command {
-        Commands::Import => {
-            println!("Running import...");
-            scanner::scan_directory(&config.directory, &conn)?;
-            println!("Import complete.");
+        Commands::Import {
+            reconcile,
+            full,
+            format,
+        } => {
+            if format == OutputFormat::Json {
+                // Stdout carries only the JSON document, with the reconcile outcome included
+                let output = ImportOutput {
+                    report: scanner::scan_directory(&config, &conn, full)?,
+                    reconcile: if reconcile {
+                        Some(reconcile::run_reconcile(&config, &conn)?)
+                    } else {
+                        None
+                    },
+                };
+                println!("{}", serde_json::to_string_pretty(&output)?);
+            } else {
+                println!("Running import...");
+                let report = scanner::scan_directory(&config, &conn, full)?;
+                report.print_table(config.scan.dst_policy);
+                println!("Import complete.");
+                if reconcile {
+                    println!("Reconciling index against disk...");
+                    reconcile::run_reconcile(&config, &conn)?.print_table();
+                }
+            }
         }
         Commands::Maintenance => {
             println!("Running import before maintenance...");
-            scanner::scan_directory(&config.directory, &conn)?;
+            let report = scanner::scan_directory(&config, &conn, false)?;
+            report.print_table(config.scan.dst_policy);
             println!("Import complete. Running maintenance...");
-            if let Some(maint_config) = config.maintenance {
-                maintenance::run_maintenance(&conn, &maint_config.quota, maint_config.dry_run)?;
+            let roots = config.roots();
+            let default_config = Default::default();
+            // Root quotas are enforced without a `maintenance` section too
+            let maint_config = config.maintenance.as_ref().or_else(|| {
+                roots
+                    .iter()
+                    .any(|root| root.quota.is_some())
+                    .then_some(&default_config)
+            });
+            if let Some(maint_config) = maint_config {
+                maintenance::run_maintenance(&conn, &roots, maint_config)?;
             } else {
                 println!("Maintenance configuration not found in config file.");
             }
         }
-        Commands::Web => {
+        Commands::Web { watch } => {
             println!("Starting web viewer...");
             let default_config = Default::default();
             let web_config = config.web_viewer.as_ref().unwrap_or(&default_config);
             let host = web_config.host.clone();
             let port = web_config.port;
-            let state = AppState::new(conn, config);
+            // Streaming is limited to the roots, so they must be valid
+            scanner::load_roots(&config)?;
+            let state = AppState::new(conn, config)?;
+            if watch {
+                let db = state.db.clone();
+                let config = state.config.clone();
+                thread::spawn(move || {
+                    if let Err(e) = watcher::watch(&config, &db) {
+                        eprintln!("Watcher stopped: {}", e);
+                    }
+                });
+            }
             let server = WebServer::new(state);
             server.start(&host, port).await?;
         }
+        Commands::Watch => {
+            watcher::watch(&config, &Mutex::new(conn))?;
+        }
+        Commands::Retime => {
+            println!("Re-deriving video times...");
+            scanner::retime(&config, &conn)?;
+        }
+        Commands::Ingest { inbox, root } => {
+            println!("Ingesting recordings from {}...", inbox);
+            ingest::run_ingest(&config, &conn, Path::new(&inbox), root.as_deref())?;
+        }
+        Commands::Reorganize {
+            layout,
+            root,
+            dry_run,
+        } => {
+            println!("Reorganizing archive...");
+            reorganize::run_reorganize(
+                &config,
+                &conn,
+                layout.as_deref(),
+                root.as_deref(),
+                dry_run,
+            )?;
+        }
+        Commands::Reconcile => {
+            println!("Reconciling index against disk...");
+            reconcile::run_reconcile(&config, &conn)?.print_table();
+        }
+        Commands::Health => {
+            let filter = VideoFilter {
+                health: Some(HealthFilter::Unhealthy),
+                ..VideoFilter::on_disk()
+            };
+            let recordings = db::query_videos(&conn, &filter)?;
+            if recordings.is_empty() {
+                println!("All indexed videos are healthy.");
+            }
+            for recording in &recordings {
+                println!("{:<10} {}", recording.health.as_str(), recording.file_path);
+            }
+        }
+        Commands::Db {
+            command: DbCommand::Migrate { .. },
+        } => {
+            println!(
+                "Database schema is at version {}.",
+                migrations::current_version(&conn)?
+            );
+        }
     }
 
     Ok(())
 }
+
+/// JSON output of `import`: the scan report, plus the reconcile report with `--reconcile`.
+#[derive(Serialize)]
+struct ImportOutput {
+    #[serde(flatten)]
+    report: ScanReport,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reconcile: Option<ReconcileReport>,
+}
+
+fn print_migration_status(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    let statuses = migrations::status(conn)?;
+    println!(
+        "Database schema version {} (this build supports {})",
+        migrations::current_version(conn)?,
+        migrations::latest_version()
+    );
+    for status in &statuses {
+        let state = match status.applied_at {
+            Some(applied_at) => format!("applied {}", applied_at.format("%Y-%m-%d %H:%M:%S")),
+            None => "pending".to_string(),
+        };
+        println!(
+            "{:>4}  {:<27}  {}",
+            status.migration.version, state, status.migration.description
+        );
+    }
+    Ok(())
+}
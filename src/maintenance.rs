@@ -1,47 +1,60 @@
-use crate::db;
+use crate::cli::{MaintenanceConfig, RootConfig};
+use crate::db::{self, VideoFilter};
+use crate::models::VideoRecording;
 use byte_unit::Byte;
 use rusqlite::Connection;
+use std::collections::HashSet;
 use std::fs;
 use std::str::FromStr;
 
+/// Deletes the oldest recordings until every root with a quota is within it, then until the
+/// archive as a whole is within the overall quota.
 pub fn run_maintenance(
     conn: &Connection,
-    quota_str: &str,
-    dry_run: bool,
+    roots: &[RootConfig],
+    maintenance: &MaintenanceConfig,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let quota = Byte::from_str(quota_str)?.as_u64();
-    let recordings = db::get_all_non_deleted_recordings(conn)?;
+    let recordings = db::query_videos(conn, &VideoFilter::on_disk())?;
+    let mut recordings_to_delete: Vec<&VideoRecording> = Vec::new();
 
-    let total_size: u64 = recordings.iter().map(|r| r.file_size).sum();
-
-    if total_size <= quota {
-        println!("Total size is within the quota. No action needed.");
-        return Ok(());
+    for root in roots {
+        if let Some(quota) = &root.quota {
+            let in_root = recordings.iter().filter(|r| r.root == root.name);
+            let over = select_over_quota(in_root, Byte::from_str(quota)?.as_u64());
+            if !over.is_empty() {
+                println!("Root '{}' is over its quota of {}.", root.name, quota);
+            }
+            recordings_to_delete.extend(over);
+        }
     }
 
-    let mut size_to_delete = total_size - quota;
-    let mut recordings_to_delete = Vec::new();
+    if let Some(quota) = &maintenance.quota {
+        let selected: HashSet<&str> = recordings_to_delete
+            .iter()
+            .map(|r| r.file_path.as_str())
+            .collect();
+        let remaining = recordings
+            .iter()
+            .filter(|r| !selected.contains(r.file_path.as_str()));
+        recordings_to_delete.extend(select_over_quota(
+            remaining,
+            Byte::from_str(quota)?.as_u64(),
+        ));
+    }
 
-    for recording in recordings {
-        if size_to_delete == 0 {
-            break;
-        }
-        if recording.file_size >= size_to_delete {
-            size_to_delete = 0;
-        } else {
-            size_to_delete -= recording.file_size;
-        }
-        recordings_to_delete.push(recording);
+    if recordings_to_delete.is_empty() {
+        println!("Total size is within the quota. No action needed.");
+        return Ok(());
     }
 
-    if dry_run {
+    if maintenance.dry_run {
         println!("-- Dry Run --");
         println!("The following files would be deleted:");
         for recording in &recordings_to_delete {
             println!("- {} ({} bytes)", recording.file_path, recording.file_size);
         }
     } else {
-        let tx = conn.unchecked_transaction()?;
+        let tx = db::begin_write(conn)?;
         for recording in &recordings_to_delete {
             println!("Deleting: {}", recording.file_path);
             fs::remove_file(&recording.file_path)?;
@@ -53,3 +66,30 @@ pub fn run_maintenance(
 
     Ok(())
 }
+
+/// Picks the oldest recordings whose removal brings the total size within `quota`.
+fn select_over_quota<'a>(
+    recordings: impl Iterator<Item = &'a VideoRecording> + Clone,
+    quota: u64,
+) -> Vec<&'a VideoRecording> {
+    let total_size: u64 = recordings.clone().map(|r| r.file_size).sum();
+    if total_size <= quota {
+        return Vec::new();
+    }
+
+    let mut size_to_delete = total_size - quota;
+    let mut recordings_to_delete = Vec::new();
+
+    for recording in recordings {
+        if size_to_delete == 0 {
+            break;
+        }
+        if recording.file_size >= size_to_delete {
+            size_to_delete = 0;
+        } else {
+            size_to_delete -= recording.file_size;
+        }
+        recordings_to_delete.push(recording);
+    }
+    recordings_to_delete
+}
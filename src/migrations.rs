@@ -0,0 +1,361 @@
+use crate::db::{self, DEFAULT_ROOT};
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, Result};
+
+/// A numbered step in the evolution of the database schema. Migrations are applied in order,
+/// each in its own transaction, and recorded in the `schema_version` table.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    apply: fn(&Connection) -> Result<()>,
+}
+
+/// Every migration, in order. Append new ones here; never change one that has been released.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Baseline schema, adopting databases created before versioning",
+        apply: baseline,
+    },
+    Migration {
+        version: 2,
+        description: "Store recording dates as YYYY-MM-DD",
+        apply: iso_dates,
+    },
+    Migration {
+        version: 3,
+        description: "Identify videos by an integer ID",
+        apply: video_ids,
+    },
+    Migration {
+        version: 4,
+        description: "Index videos for filtered queries",
+        apply: filter_indexes,
+    },
+    Migration {
+        version: 5,
+        description: "Clear stream and trigger guessed from file names",
+        apply: clear_guessed_flags,
+    },
+];
+
+/// Whether a migration has been applied to a database, for `db migrate --status`.
+pub struct MigrationStatus {
+    pub migration: &'static Migration,
+    pub applied_at: Option<DateTime<Utc>>,
+}
+
+/// Schema version this build of reopal expects.
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+/// Returns the schema version of the database, 0 if it has never been migrated.
+pub fn current_version(conn: &Connection) -> Result<u32> {
+    let exists: Option<String> = conn
+        .query_row(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'schema_version'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if exists.is_none() {
+        return Ok(0);
+    }
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Applies the pending migrations, returning those applied. Refuses to touch a database whose
+/// schema is newer than this build, as written by a later release.
+pub fn migrate(conn: &Connection) -> Result<Vec<&'static Migration>, Box<dyn std::error::Error>> {
+    let current = check_version(conn)?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at DATETIME NOT NULL
+        )",
+        [],
+    )?;
+
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let tx = db::begin_write(conn)?;
+        (migration.apply)(&tx).map_err(|e| {
+            format!(
+                "Schema migration {} ({}) failed: {}",
+                migration.version, migration.description, e
+            )
+        })?;
+        tx.execute(
+            "INSERT INTO schema_version (version, description, applied_at) VALUES (?1, ?2, ?3)",
+            rusqlite::params![migration.version, migration.description, Utc::now()],
+        )?;
+        tx.commit()?;
+        applied.push(migration);
+    }
+    Ok(applied)
+}
+
+/// Lists every migration this build knows with the time it was applied to the database.
+pub fn status(conn: &Connection) -> Result<Vec<MigrationStatus>, Box<dyn std::error::Error>> {
+    let current = check_version(conn)?;
+    let mut statuses = Vec::new();
+    for migration in MIGRATIONS {
+        let applied_at = if migration.version <= current {
+            conn.query_row(
+                "SELECT applied_at FROM schema_version WHERE version = ?1",
+                [migration.version],
+                |row| row.get(0),
+            )
+            .optional()?
+        } else {
+            None
+        };
+        statuses.push(MigrationStatus {
+            migration,
+            applied_at,
+        });
+    }
+    Ok(statuses)
+}
+
+/// Returns the schema version of the database, or an error if it is newer than this build.
+fn check_version(conn: &Connection) -> Result<u32, Box<dyn std::error::Error>> {
+    let current = current_version(conn)?;
+    if current > latest_version() {
+        return Err(format!(
+            "Database schema version {} is newer than this build of reopal supports ({}); upgrade reopal",
+            current,
+            latest_version()
+        )
+        .into());
+    }
+    Ok(current)
+}
+
+/// Brings a database created before schema versioning, or a new one, to the schema of the
+/// first versioned release. Databases of that era are told apart by their columns.
+fn baseline(conn: &Connection) -> Result<()> {
+    // Databases from before DATETIME time columns are rebuilt
+    let mut has_old_schema = false;
+    let stmt = conn.prepare("PRAGMA table_info(videos)");
+    if let Ok(mut stmt) = stmt {
+        let rows = stmt.query_map([], |row| {
+            let column_name: String = row.get(1)?;
+            let column_type: String = row.get(2)?;
+            Ok((column_name, column_type))
+        });
+
+        if let Ok(rows) = rows {
+            for (name, type_name) in rows.flatten() {
+                if (name == "start_time" || name == "end_time") && type_name == "TEXT" {
+                    has_old_schema = true;
+                    break;
+                }
+            }
+        }
+    }
+
+    if has_old_schema {
+        // Migrate existing table
+        conn.execute("ALTER TABLE videos RENAME TO videos_old", [])?;
+        conn.execute(
+            "CREATE TABLE videos (
+                file_path TEXT PRIMARY KEY,
+                camera_name TEXT NOT NULL,
+                date TEXT NOT NULL,
+                start_time DATETIME NOT NULL,
+                end_time DATETIME NOT NULL,
+                file_size INTEGER NOT NULL,
+                deleted BOOLEAN NOT NULL DEFAULT 0
+            )",
+            [],
+        )?;
+
+        // Migrate data from old table to new table
+        conn.execute(
+            "INSERT INTO videos (file_path, camera_name, date, start_time, end_time, file_size, deleted)
+             SELECT file_path, camera_name, date, datetime(start_time), datetime(end_time), file_size, deleted
+             FROM videos_old",
+            [],
+        )?;
+
+        // Drop the old table
+        conn.execute("DROP TABLE videos_old", [])?;
+    } else {
+        // Create new table with DATETIME columns
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS videos (
+                file_path TEXT PRIMARY KEY,
+                camera_name TEXT NOT NULL,
+                date TEXT NOT NULL,
+                start_time DATETIME NOT NULL,
+                end_time DATETIME NOT NULL,
+                file_size INTEGER NOT NULL,
+                deleted BOOLEAN NOT NULL DEFAULT 0
+            )",
+            [],
+        )?;
+    }
+
+    add_column_if_missing(conn, "time_flag", "TEXT")?;
+    // Rows indexed before the column existed took their times from the file's birth time
+    add_column_if_missing(conn, "time_source", "TEXT NOT NULL DEFAULT 'birth_time'")?;
+    add_column_if_missing(conn, "duration", "REAL")?;
+    add_column_if_missing(conn, "width", "INTEGER")?;
+    add_column_if_missing(conn, "height", "INTEGER")?;
+    add_column_if_missing(conn, "codec", "TEXT")?;
+    add_column_if_missing(conn, "frame_rate", "REAL")?;
+    add_column_if_missing(conn, "bitrate", "INTEGER")?;
+    add_column_if_missing(conn, "health", "TEXT NOT NULL DEFAULT 'ok'")?;
+    add_column_if_missing(conn, "file_mtime", "DATETIME")?;
+    add_column_if_missing(
+        conn,
+        "root",
+        &format!("TEXT NOT NULL DEFAULT '{}'", DEFAULT_ROOT),
+    )?;
+    add_column_if_missing(conn, "channel", "INTEGER")?;
+    add_column_if_missing(conn, "stream", "TEXT")?;
+    add_column_if_missing(conn, "trigger", "TEXT")?;
+    if add_column_if_missing(conn, "raw_camera_name", "TEXT")? {
+        // No aliases were applied before, so the names indexed so far are the raw names
+        conn.execute("UPDATE videos SET raw_camera_name = camera_name", [])?;
+    }
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_videos_raw_camera_name ON videos (raw_camera_name)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS scan_checkpoints (
+            root TEXT PRIMARY KEY,
+            directory TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS directory_watermarks (
+            directory TEXT PRIMARY KEY,
+            mtime DATETIME NOT NULL,
+            file_count INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS reorganize_journal (
+            old_path TEXT PRIMARY KEY,
+            new_path TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Adds a column to the 'videos' table for databases created before it existed, returning
+/// whether it was added.
+fn add_column_if_missing(conn: &Connection, column: &str, definition: &str) -> Result<bool> {
+    let mut stmt = conn.prepare("PRAGMA table_info(videos)")?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .flatten()
+        .any(|name| name == column);
+
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE videos ADD COLUMN {} {}", column, definition),
+            [],
+        )?;
+    }
+    Ok(!exists)
+}
+
+/// Rewrites `MMDDYYYY` dates as `YYYY-MM-DD`, which sort and compare in date order.
+fn iso_dates(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "UPDATE videos
+         SET date = substr(date, 5, 4) || '-' || substr(date, 1, 2) || '-' || substr(date, 3, 2)
+         WHERE length(date) = 8",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Rebuilds the 'videos' table around an integer primary key, so that videos keep their ID
+/// when their file moves. The file path stays unique.
+fn video_ids(conn: &Connection) -> Result<()> {
+    conn.execute(
+        &format!(
+            "CREATE TABLE videos_new (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                file_path TEXT NOT NULL UNIQUE,
+                camera_name TEXT NOT NULL,
+                date TEXT NOT NULL,
+                start_time DATETIME NOT NULL,
+                end_time DATETIME NOT NULL,
+                file_size INTEGER NOT NULL,
+                deleted BOOLEAN NOT NULL DEFAULT 0,
+                time_flag TEXT,
+                time_source TEXT NOT NULL DEFAULT 'birth_time',
+                duration REAL,
+                width INTEGER,
+                height INTEGER,
+                codec TEXT,
+                frame_rate REAL,
+                bitrate INTEGER,
+                health TEXT NOT NULL DEFAULT 'ok',
+                file_mtime DATETIME,
+                root TEXT NOT NULL DEFAULT '{}',
+                channel INTEGER,
+                stream TEXT,
+                trigger TEXT,
+                raw_camera_name TEXT
+            )",
+            DEFAULT_ROOT
+        ),
+        [],
+    )?;
+
+    // Number existing videos in recording order
+    let columns = "file_path, camera_name, date, start_time, end_time, file_size, deleted,
+        time_flag, time_source, duration, width, height, codec, frame_rate, bitrate, health,
+        file_mtime, root, channel, stream, trigger, raw_camera_name";
+    conn.execute(
+        &format!(
+            "INSERT INTO videos_new ({0}) SELECT {0} FROM videos ORDER BY date, start_time",
+            columns
+        ),
+        [],
+    )?;
+    conn.execute("DROP TABLE videos", [])?;
+    conn.execute("ALTER TABLE videos_new RENAME TO videos", [])?;
+    conn.execute(
+        "CREATE INDEX idx_videos_raw_camera_name ON videos (raw_camera_name)",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Indexes the columns `db::query_videos` filters and sorts on. Listings are ordered by start
+/// time, so the camera, root and trigger indexes include it to serve the sort as well.
+fn filter_indexes(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE INDEX idx_videos_start_time ON videos (start_time);
+         CREATE INDEX idx_videos_camera_start_time ON videos (camera_name, start_time);
+         CREATE INDEX idx_videos_root_start_time ON videos (root, start_time);
+         CREATE INDEX idx_videos_trigger_start_time ON videos (trigger, start_time);
+         CREATE INDEX idx_videos_date ON videos (date);",
+    )
+}
+
+/// Clears the stream and trigger that earlier versions decoded from the channel field of FTP
+/// file names, which does not record them. `retime` re-derives the channel, and the stream and
+/// trigger of files named by a pattern scheme with `stream` and `trigger` captures.
+fn clear_guessed_flags(conn: &Connection) -> Result<()> {
+    conn.execute("UPDATE videos SET stream = NULL, trigger = NULL", [])?;
+    Ok(())
+}
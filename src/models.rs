@@ -1,12 +1,212 @@
 use chrono::{DateTime, Utc};
+use serde::Deserialize;
 
 #[derive(Debug)]
 pub struct VideoRecording {
+    /// Row ID in the database; `None` until the recording is stored.
+    pub id: Option<i64>,
+    /// Camera name after applying the configured aliases.
     pub camera_name: String,
+    /// Camera name as found in the file name.
+    pub raw_camera_name: String,
+    /// Recording date as `YYYY-MM-DD`.
     pub date: String,
     pub start_time: DateTime<Utc>,
     pub end_time: DateTime<Utc>,
     pub file_path: String,
+    /// Name of the archive root the file was found under.
+    pub root: String,
     pub file_size: u64,
+    /// Modification time of the file when it was indexed, used to detect changes on re-scan.
+    pub file_mtime: Option<DateTime<Utc>>,
     pub deleted: bool,
+    /// Set when the local recording time did not map to exactly one instant.
+    pub time_flag: Option<TimeFlag>,
+    /// Where the recording times were taken from.
+    pub time_source: TimestampSource,
+    pub media: MediaInfo,
+    pub health: VideoHealth,
+    pub flags: RecordingFlags,
+}
+
+/// Channel, stream and trigger of a recording; absent when the file name does not record them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecordingFlags {
+    pub channel: Option<u8>,
+    pub stream: Option<Stream>,
+    pub trigger: Option<Trigger>,
+}
+
+impl RecordingFlags {
+    /// Decodes the second field of Reolink FTP file names (the `00` in `Camera-00-...`), the
+    /// recorder channel as two decimal digits: `00` for a standalone camera. The field does not
+    /// record the stream or trigger, so those are left unset rather than guessed.
+    pub fn decode(field: &str) -> Option<Self> {
+        if field.len() != 2 || !field.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        Some(Self {
+            channel: Some(field.parse().ok()?),
+            ..Default::default()
+        })
+    }
+}
+
+/// Which of the camera's encoder streams was recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    /// Full resolution ("clear").
+    Main,
+    /// Reduced resolution ("fluent").
+    Sub,
+}
+
+impl Stream {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stream::Main => "main",
+            Stream::Sub => "sub",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "main" => Some(Stream::Main),
+            "sub" => Some(Stream::Sub),
+            _ => None,
+        }
+    }
+}
+
+/// What caused the camera to record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// Scheduled continuous recording.
+    Timer,
+    Motion,
+    Person,
+    Vehicle,
+}
+
+impl Trigger {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Trigger::Timer => "timer",
+            Trigger::Motion => "motion",
+            Trigger::Person => "person",
+            Trigger::Vehicle => "vehicle",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "timer" => Some(Trigger::Timer),
+            "motion" => Some(Trigger::Motion),
+            "person" => Some(Trigger::Person),
+            "vehicle" => Some(Trigger::Vehicle),
+            _ => None,
+        }
+    }
+}
+
+/// Integrity of a recording's MP4 container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoHealth {
+    Ok,
+    /// The container is cut short, typically with no `moov` box after a power cut.
+    Truncated,
+    /// The file could not be read or is not an MP4 container.
+    Unreadable,
+}
+
+impl VideoHealth {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VideoHealth::Ok => "ok",
+            VideoHealth::Truncated => "truncated",
+            VideoHealth::Unreadable => "unreadable",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "ok" => Some(VideoHealth::Ok),
+            "truncated" => Some(VideoHealth::Truncated),
+            "unreadable" => Some(VideoHealth::Unreadable),
+            _ => None,
+        }
+    }
+}
+
+/// Properties read from the MP4 container; absent when the header could not be parsed.
+#[derive(Debug, Clone, Default)]
+pub struct MediaInfo {
+    pub duration: Option<f64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub codec: Option<String>,
+    pub frame_rate: Option<f64>,
+    /// Average bitrate in bits per second.
+    pub bitrate: Option<u64>,
+}
+
+/// A source of recording times, tried in the order configured by `scan.timestamp_sources`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampSource {
+    /// Directory date plus the start/end times in the file name.
+    Filename,
+    /// File creation time, taken as the end of the recording.
+    BirthTime,
+    /// File modification time, taken as the end of the recording.
+    Mtime,
+    /// Creation time in the MP4 movie header, taken as the start of the recording.
+    Mp4Header,
+}
+
+impl TimestampSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimestampSource::Filename => "filename",
+            TimestampSource::BirthTime => "birth_time",
+            TimestampSource::Mtime => "mtime",
+            TimestampSource::Mp4Header => "mp4_header",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "filename" => Some(TimestampSource::Filename),
+            "birth_time" => Some(TimestampSource::BirthTime),
+            "mtime" => Some(TimestampSource::Mtime),
+            "mp4_header" => Some(TimestampSource::Mp4Header),
+            _ => None,
+        }
+    }
+}
+
+/// Why a recording's local time needed a DST policy to resolve.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeFlag {
+    /// The time occurred twice (clocks fell back).
+    Ambiguous,
+    /// The time was skipped (clocks sprang forward).
+    Nonexistent,
+}
+
+impl TimeFlag {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeFlag::Ambiguous => "ambiguous",
+            TimeFlag::Nonexistent => "nonexistent",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "ambiguous" => Some(TimeFlag::Ambiguous),
+            "nonexistent" => Some(TimeFlag::Nonexistent),
+            _ => None,
+        }
+    }
 }
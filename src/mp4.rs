@@ -0,0 +1,476 @@
+use crate::models::VideoHealth;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Values read from the movie header box (`moov/mvhd`) and the first video track.
+#[derive(Debug, Clone)]
+pub struct Mp4Info {
+    pub creation_time: Option<DateTime<Utc>>,
+    pub timescale: u32,
+    pub duration: u64,
+    pub video: Option<VideoTrack>,
+}
+
+/// Properties of a video track (`trak` with a `vide` handler).
+#[derive(Debug, Clone)]
+pub struct VideoTrack {
+    pub width: u32,
+    pub height: u32,
+    /// Sample entry type from `stsd`, e.g. `avc1` or `hvc1`.
+    pub format: String,
+    pub frame_rate: Option<f64>,
+}
+
+impl Mp4Info {
+    /// Duration of the movie in seconds, if the timescale is valid.
+    pub fn duration_seconds(&self) -> Option<f64> {
+        (self.timescale > 0).then(|| self.duration as f64 / self.timescale as f64)
+    }
+}
+
+impl VideoTrack {
+    /// Codec name for the sample entry type, falling back to the raw type.
+    pub fn codec(&self) -> String {
+        match self.format.as_str() {
+            "avc1" | "avc3" => "h264".to_string(),
+            "hvc1" | "hev1" => "h265".to_string(),
+            other => other.to_string(),
+        }
+    }
+}
+
+/// Header of an ISO base media file format box.
+#[derive(Debug, Clone, Copy)]
+struct BoxHeader {
+    kind: [u8; 4],
+    /// Offset of the box payload.
+    payload_start: u64,
+    /// Offset one past the end of the box.
+    end: u64,
+}
+
+/// Checks the container structure of an MP4 file, returning its health and, when
+/// intact, the movie information.
+pub fn probe(path: &Path) -> (VideoHealth, Option<Mp4Info>) {
+    match read_info(path) {
+        Ok(Some(info)) => (VideoHealth::Ok, Some(info)),
+        // A recording cut off before the `moov` box was written
+        Ok(None) => (VideoHealth::Truncated, None),
+        Err(e)
+            if e.kind() == io::ErrorKind::UnexpectedEof
+                || e.kind() == io::ErrorKind::InvalidData =>
+        {
+            (VideoHealth::Truncated, None)
+        }
+        Err(_) => (VideoHealth::Unreadable, None),
+    }
+}
+
+/// Reads the movie header and video track of an MP4 file, returning `None` if it has no `moov/mvhd`.
+pub fn read_info(path: &Path) -> io::Result<Option<Mp4Info>> {
+    let file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    read_info_from(&mut BufReader::new(file), file_len)
+}
+
+/// Reads the movie information from the first `file_len` bytes of `reader`.
+fn read_info_from<R: Read + Seek>(reader: &mut R, file_len: u64) -> io::Result<Option<Mp4Info>> {
+    // Anything not starting with a plausible box type is not an MP4 file at all
+    if let Some(first) = read_box_header(reader, 0, u64::MAX)? {
+        if !first.kind.iter().all(|b| b.is_ascii_alphanumeric()) {
+            return Err(io::Error::other("file does not start with an MP4 box"));
+        }
+    }
+
+    let Some(moov) = find_box(reader, 0, file_len, b"moov")? else {
+        return Ok(None);
+    };
+    let Some(mvhd) = find_box(reader, moov.payload_start, moov.end, b"mvhd")? else {
+        return Ok(None);
+    };
+
+    reader.seek(SeekFrom::Start(mvhd.payload_start))?;
+    let version = read_u8(reader)?;
+    skip(reader, 3)?; // flags
+
+    let (creation_time, timescale, duration) = if version == 1 {
+        let creation_time = read_u64(reader)?;
+        skip(reader, 8)?; // modification_time
+        (creation_time, read_u32(reader)?, read_u64(reader)?)
+    } else {
+        let creation_time = read_u32(reader)? as u64;
+        skip(reader, 4)?; // modification_time
+        (creation_time, read_u32(reader)?, read_u32(reader)? as u64)
+    };
+
+    let mut video = None;
+    let mut offset = moov.payload_start;
+    while let Some(header) = read_box_header(reader, offset, moov.end)? {
+        if &header.kind == b"trak" {
+            video = read_video_track(reader, header)?;
+            if video.is_some() {
+                break;
+            }
+        }
+        offset = header.end;
+    }
+
+    Ok(Some(Mp4Info {
+        creation_time: mp4_time_to_utc(creation_time),
+        timescale,
+        duration,
+        video,
+    }))
+}
+
+/// Reads a `trak` box, returning `None` unless it is a video track.
+fn read_video_track<R: Read + Seek>(
+    reader: &mut R,
+    trak: BoxHeader,
+) -> io::Result<Option<VideoTrack>> {
+    let Some(mdia) = find_box(reader, trak.payload_start, trak.end, b"mdia")? else {
+        return Ok(None);
+    };
+    let Some(hdlr) = find_box(reader, mdia.payload_start, mdia.end, b"hdlr")? else {
+        return Ok(None);
+    };
+    reader.seek(SeekFrom::Start(add(hdlr.payload_start, 8)?))?; // version, flags, pre_defined
+    let mut handler = [0u8; 4];
+    reader.read_exact(&mut handler)?;
+    if &handler != b"vide" {
+        return Ok(None);
+    }
+
+    // Track dimensions are 16.16 fixed point at the end of `tkhd`
+    let (mut width, mut height) = (0, 0);
+    if let Some(tkhd) = find_box(reader, trak.payload_start, trak.end, b"tkhd")? {
+        reader.seek(SeekFrom::Start(tkhd.payload_start))?;
+        let version = read_u8(reader)?;
+        let dimensions_offset = if version == 1 { 88 } else { 76 };
+        reader.seek(SeekFrom::Start(add(tkhd.payload_start, dimensions_offset)?))?;
+        width = read_u32(reader)? >> 16;
+        height = read_u32(reader)? >> 16;
+    }
+
+    // Media timescale and duration, for the frame rate
+    let mut media_seconds = None;
+    if let Some(mdhd) = find_box(reader, mdia.payload_start, mdia.end, b"mdhd")? {
+        reader.seek(SeekFrom::Start(mdhd.payload_start))?;
+        let version = read_u8(reader)?;
+        skip(reader, 3)?; // flags
+        let (timescale, duration) = if version == 1 {
+            skip(reader, 16)?; // creation_time, modification_time
+            (read_u32(reader)?, read_u64(reader)?)
+        } else {
+            skip(reader, 8)?; // creation_time, modification_time
+            (read_u32(reader)?, read_u32(reader)? as u64)
+        };
+        if timescale > 0 && duration > 0 {
+            media_seconds = Some(duration as f64 / timescale as f64);
+        }
+    }
+
+    let Some(minf) = find_box(reader, mdia.payload_start, mdia.end, b"minf")? else {
+        return Ok(None);
+    };
+    let Some(stbl) = find_box(reader, minf.payload_start, minf.end, b"stbl")? else {
+        return Ok(None);
+    };
+
+    let mut format = String::new();
+    if let Some(stsd) = find_box(reader, stbl.payload_start, stbl.end, b"stsd")? {
+        // version, flags and entry_count precede the first sample entry
+        if let Some(entry) = read_box_header(reader, add(stsd.payload_start, 8)?, stsd.end)? {
+            format = String::from_utf8_lossy(&entry.kind).to_string();
+            if width == 0 || height == 0 {
+                // Visual sample entries store 16-bit dimensions after 24 bytes of fields
+                reader.seek(SeekFrom::Start(add(entry.payload_start, 24)?))?;
+                width = read_u16(reader)? as u32;
+                height = read_u16(reader)? as u32;
+            }
+        }
+    }
+
+    let mut frame_rate = None;
+    if let Some(stts) = find_box(reader, stbl.payload_start, stbl.end, b"stts")? {
+        // Never read past the box, whatever its entry count claims
+        reader.seek(SeekFrom::Start(add(stts.payload_start, 4)?))?; // version, flags
+        let max_entries = (stts.end - stts.payload_start).saturating_sub(8) / 8;
+        let entry_count = u64::from(read_u32(reader)?).min(max_entries);
+        let mut samples = 0u64;
+        let mut entry = [0u8; 8]; // sample_count, sample_delta
+        for _ in 0..entry_count {
+            reader.read_exact(&mut entry)?;
+            let sample_count = u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]);
+            samples = samples.saturating_add(sample_count.into());
+        }
+        frame_rate = media_seconds.map(|seconds| samples as f64 / seconds);
+    }
+
+    Ok(Some(VideoTrack {
+        width,
+        height,
+        format,
+        frame_rate,
+    }))
+}
+
+/// Finds the first box of the given type among the siblings in `start..end`.
+fn find_box<R: Read + Seek>(
+    reader: &mut R,
+    start: u64,
+    end: u64,
+    kind: &[u8; 4],
+) -> io::Result<Option<BoxHeader>> {
+    let mut offset = start;
+    while let Some(header) = read_box_header(reader, offset, end)? {
+        if &header.kind == kind {
+            return Ok(Some(header));
+        }
+        offset = header.end;
+    }
+    Ok(None)
+}
+
+/// Reads the box header at `offset`, returning `None` at the end of the container.
+fn read_box_header<R: Read + Seek>(
+    reader: &mut R,
+    offset: u64,
+    end: u64,
+) -> io::Result<Option<BoxHeader>> {
+    if add(offset, 8)? > end {
+        return Ok(None);
+    }
+
+    reader.seek(SeekFrom::Start(offset))?;
+    let size = read_u32(reader)? as u64;
+    let mut kind = [0u8; 4];
+    reader.read_exact(&mut kind)?;
+
+    let (payload_start, box_end) = match size {
+        // Box extends to the end of its container
+        0 => (offset + 8, end),
+        // 64-bit size follows the type
+        1 => (add(offset, 16)?, add(offset, read_u64(reader)?)?),
+        _ => (offset + 8, add(offset, size)?),
+    };
+
+    if box_end < payload_start || box_end > end {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "box '{}' at offset {} overruns its container",
+                String::from_utf8_lossy(&kind),
+                offset
+            ),
+        ));
+    }
+
+    Ok(Some(BoxHeader {
+        kind,
+        payload_start,
+        end: box_end,
+    }))
+}
+
+/// MP4 timestamps count seconds since 1904-01-01 UTC; zero means unset.
+fn mp4_time_to_utc(seconds: u64) -> Option<DateTime<Utc>> {
+    if seconds == 0 {
+        return None;
+    }
+    let epoch = Utc.with_ymd_and_hms(1904, 1, 1, 0, 0, 0).single()?;
+    epoch.checked_add_signed(Duration::seconds(i64::try_from(seconds).ok()?))
+}
+
+/// Adds a size read from the file to an offset, failing on overflow instead of wrapping.
+fn add(offset: u64, size: u64) -> io::Result<u64> {
+    offset.checked_add(size).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("box size {} at offset {} is out of range", size, offset),
+        )
+    })
+}
+
+fn skip<R: Seek>(reader: &mut R, bytes: i64) -> io::Result<()> {
+    reader.seek(SeekFrom::Current(bytes))?;
+    Ok(())
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut buf = [0u8; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    let mut buf = [0u8; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn full_box(kind: &[u8; 4], fields: &[&[u8]]) -> Vec<u8> {
+        let mut payload = vec![0u8; 4]; // version, flags
+        for field in fields {
+            payload.extend_from_slice(field);
+        }
+        mp4_box(kind, &payload)
+    }
+
+    fn ftyp() -> Vec<u8> {
+        mp4_box(b"ftyp", b"isom\0\0\x02\0isomavc1")
+    }
+
+    /// A 10 second movie created at 2024-01-31 08:30:00 UTC.
+    fn mvhd() -> Vec<u8> {
+        let creation_time = 3_789_534_600u32;
+        full_box(
+            b"mvhd",
+            &[
+                &creation_time.to_be_bytes(),
+                &creation_time.to_be_bytes(),
+                &1000u32.to_be_bytes(),
+                &10_000u32.to_be_bytes(),
+            ],
+        )
+    }
+
+    /// A 1920x1080 H.264 track of 250 frames over 10 seconds, with an `stts` entry count.
+    fn video_trak(stts_entry_count: u32) -> Vec<u8> {
+        let mut tkhd = vec![0u8; 76];
+        tkhd.extend_from_slice(&(1920u32 << 16).to_be_bytes());
+        tkhd.extend_from_slice(&(1080u32 << 16).to_be_bytes());
+        let tkhd = full_box(b"tkhd", &[&tkhd[4..]]);
+
+        let mdhd = full_box(
+            b"mdhd",
+            &[
+                &[0u8; 8],
+                &90_000u32.to_be_bytes(),
+                &900_000u32.to_be_bytes(),
+            ],
+        );
+        let hdlr = full_box(b"hdlr", &[&[0u8; 4], b"vide", &[0u8; 12]]);
+        let avc1 = mp4_box(b"avc1", &[0u8; 70]);
+        let stsd = full_box(b"stsd", &[&1u32.to_be_bytes(), &avc1]);
+        let stts = full_box(
+            b"stts",
+            &[
+                &stts_entry_count.to_be_bytes(),
+                &200u32.to_be_bytes(),
+                &3600u32.to_be_bytes(),
+                &50u32.to_be_bytes(),
+                &3600u32.to_be_bytes(),
+            ],
+        );
+        let stbl = mp4_box(b"stbl", &[stsd, stts].concat());
+        let minf = mp4_box(b"minf", &stbl);
+        let mdia = mp4_box(b"mdia", &[mdhd, hdlr, minf].concat());
+        mp4_box(b"trak", &[tkhd, mdia].concat())
+    }
+
+    fn moov() -> Vec<u8> {
+        mp4_box(b"moov", &[mvhd(), video_trak(2)].concat())
+    }
+
+    fn read(data: &[u8]) -> io::Result<Option<Mp4Info>> {
+        read_info_from(&mut Cursor::new(data), data.len() as u64)
+    }
+
+    fn assert_movie(info: Option<Mp4Info>) {
+        let info = info.expect("movie information");
+        assert_eq!(
+            info.creation_time,
+            Utc.with_ymd_and_hms(2024, 1, 31, 8, 30, 0).single()
+        );
+        assert_eq!(info.duration_seconds(), Some(10.0));
+        let video = info.video.expect("video track");
+        assert_eq!((video.width, video.height), (1920, 1080));
+        assert_eq!(video.codec(), "h264");
+        assert_eq!(video.frame_rate, Some(25.0));
+    }
+
+    #[test]
+    fn valid_movie() {
+        let data = [ftyp(), mp4_box(b"mdat", &[0u8; 32]), moov()].concat();
+        assert_movie(read(&data).unwrap());
+    }
+
+    #[test]
+    fn missing_moov() {
+        let data = [ftyp(), mp4_box(b"mdat", &[0u8; 32])].concat();
+        assert!(read(&data).unwrap().is_none());
+    }
+
+    #[test]
+    fn largesize_box() {
+        let mut mdat = 1u32.to_be_bytes().to_vec();
+        mdat.extend_from_slice(b"mdat");
+        mdat.extend_from_slice(&48u64.to_be_bytes());
+        mdat.extend_from_slice(&[0u8; 32]);
+        let data = [ftyp(), mdat, moov()].concat();
+        assert_movie(read(&data).unwrap());
+    }
+
+    #[test]
+    fn box_overrunning_container() {
+        // An `mdat` cut short by a power cut claims more data than the file holds
+        let mut mdat = mp4_box(b"mdat", &[0u8; 32]);
+        mdat[..4].copy_from_slice(&4096u32.to_be_bytes());
+        let data = [ftyp(), mdat].concat();
+        let error = read(&data).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn largesize_overflow() {
+        let mut mdat = 1u32.to_be_bytes().to_vec();
+        mdat.extend_from_slice(b"mdat");
+        mdat.extend_from_slice(&u64::MAX.to_be_bytes());
+        let data = [ftyp(), mdat, moov()].concat();
+        let error = read(&data).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn zero_sized_last_box() {
+        // A size of zero extends the last box to the end of the file
+        let mut moov = moov();
+        moov[..4].copy_from_slice(&0u32.to_be_bytes());
+        let data = [ftyp(), mp4_box(b"mdat", &[0u8; 32]), moov].concat();
+        assert_movie(read(&data).unwrap());
+    }
+
+    #[test]
+    fn stts_entry_count_beyond_box() {
+        let moov = mp4_box(b"moov", &[mvhd(), video_trak(u32::MAX)].concat());
+        let data = [ftyp(), moov].concat();
+        assert_movie(read(&data).unwrap());
+    }
+}
@@ -0,0 +1,537 @@
+use crate::cli::{NamingConfig, PatternConfig};
+use crate::models::{RecordingFlags, Stream, Trigger};
+use chrono::{FixedOffset, NaiveDate, NaiveDateTime, NaiveTime};
+use regex::Regex;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// Recording metadata recovered from a file's name and its location in the archive.
+#[derive(Debug, Clone)]
+pub struct ParsedName {
+    pub camera_name: String,
+    pub date: NaiveDate,
+    pub start: NaiveTime,
+    /// Not every layout encodes the end of the recording.
+    pub end: Option<NaiveTime>,
+    /// UTC offset, for layouts that record it.
+    pub offset: Option<FixedOffset>,
+    pub flags: RecordingFlags,
+}
+
+/// A file naming and directory layout convention used by a recorder.
+pub trait NamingScheme: Send + Sync {
+    /// Name used to select the scheme from the configuration.
+    fn name(&self) -> &str;
+
+    /// Parses a path relative to the archive root, returning `None` if it does not follow the scheme.
+    fn parse(&self, relative: &Path) -> Option<ParsedName>;
+}
+
+/// FTP uploads from older firmware: `MMDDYYYY/<camera>-00-<HHMMSS>-<HHMMSS>.mp4`.
+pub struct ReolinkFtp;
+
+impl NamingScheme for ReolinkFtp {
+    fn name(&self) -> &str {
+        "reolink_ftp"
+    }
+
+    fn parse(&self, relative: &Path) -> Option<ParsedName> {
+        let file_name = relative.file_stem()?.to_str()?;
+        let parent_dir = relative.parent()?.file_name()?.to_str()?;
+
+        // Validate directory name format (MMDDYYYY)
+        if parent_dir.len() != 8 || !parent_dir.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let date = NaiveDate::parse_from_str(parent_dir, "%m%d%Y").ok()?;
+
+        // Parse filename format: <camera name>-00-<start time>-<end time>
+        let parts: Vec<&str> = file_name.split('-').collect();
+        if parts.len() != 4 {
+            return None;
+        }
+
+        Some(ParsedName {
+            camera_name: parts[0].to_string(),
+            date,
+            start: parse_hhmmss(parts[2])?,
+            end: Some(parse_hhmmss(parts[3])?),
+            offset: None,
+            flags: RecordingFlags::decode(parts[1]).unwrap_or_default(),
+        })
+    }
+}
+
+/// FTP uploads from newer firmware: `<camera>_00_<YYYYMMDDHHMMSS>.mp4` in any directory.
+pub struct ReolinkFtpV2;
+
+impl NamingScheme for ReolinkFtpV2 {
+    fn name(&self) -> &str {
+        "reolink_ftp_v2"
+    }
+
+    fn parse(&self, relative: &Path) -> Option<ParsedName> {
+        let file_name = relative.file_stem()?.to_str()?;
+
+        // Camera names may contain underscores, so split from the right
+        let parts: Vec<&str> = file_name.rsplitn(3, '_').collect();
+        if parts.len() != 3 || parts[0].len() != 14 || parts[1].len() != 2 {
+            return None;
+        }
+
+        let timestamp = NaiveDateTime::parse_from_str(parts[0], "%Y%m%d%H%M%S").ok()?;
+        Some(ParsedName {
+            camera_name: parts[2].to_string(),
+            date: timestamp.date(),
+            start: timestamp.time(),
+            end: None,
+            offset: None,
+            flags: RecordingFlags::decode(parts[1]).unwrap_or_default(),
+        })
+    }
+}
+
+/// Camera SD-card and NVR dumps: `Mp4Record/YYYY-MM-DD/RecM01_<YYYYMMDD>_<HHMMSS>_<HHMMSS>_...mp4`.
+///
+/// The camera name is taken from the directory containing `Mp4Record`, falling back to the
+/// file prefix (e.g. `RecM01`) when the dump sits directly in the archive root.
+pub struct ReolinkSdCard;
+
+impl NamingScheme for ReolinkSdCard {
+    fn name(&self) -> &str {
+        "reolink_sdcard"
+    }
+
+    fn parse(&self, relative: &Path) -> Option<ParsedName> {
+        let file_name = relative.file_stem()?.to_str()?;
+        let parent = relative.parent()?;
+        let parent_dir = parent.file_name()?.to_str()?;
+        NaiveDate::parse_from_str(parent_dir, "%Y-%m-%d").ok()?;
+
+        let parts: Vec<&str> = file_name.split('_').collect();
+        if parts.len() < 4 || !parts[0].starts_with("Rec") {
+            return None;
+        }
+        let date = NaiveDate::parse_from_str(parts[1], "%Y%m%d").ok()?;
+
+        let camera_name = parent
+            .parent()
+            .filter(|dir| dir.file_name().is_some_and(|n| n == "Mp4Record"))
+            .and_then(|dir| dir.parent())
+            .and_then(|dir| dir.file_name())
+            .and_then(|n| n.to_str())
+            .unwrap_or(parts[0]);
+
+        Some(ParsedName {
+            camera_name: camera_name.to_string(),
+            date,
+            start: parse_hhmmss(parts[2])?,
+            end: Some(parse_hhmmss(parts[3])?),
+            offset: None,
+            flags: RecordingFlags::default(),
+        })
+    }
+}
+
+/// A user-defined scheme matching the relative path against a regex with named captures.
+pub struct PatternScheme {
+    name: String,
+    regex: Regex,
+    date_format: String,
+    time_format: String,
+}
+
+impl PatternScheme {
+    pub fn new(config: &PatternConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let regex = Regex::new(&config.regex)
+            .map_err(|e| format!("Invalid regex for naming pattern '{}': {}", config.name, e))?;
+
+        for group in ["camera", "date", "start"] {
+            if !regex.capture_names().any(|n| n == Some(group)) {
+                return Err(format!(
+                    "Naming pattern '{}' is missing the '{}' named capture",
+                    config.name, group
+                )
+                .into());
+            }
+        }
+
+        Ok(Self {
+            name: config.name.clone(),
+            regex,
+            date_format: config.date_format.clone(),
+            time_format: config.time_format.clone(),
+        })
+    }
+}
+
+impl NamingScheme for PatternScheme {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn parse(&self, relative: &Path) -> Option<ParsedName> {
+        let path = to_slash_path(relative)?;
+        let captures = self.regex.captures(&path)?;
+
+        let date = NaiveDate::parse_from_str(&captures["date"], &self.date_format).ok()?;
+        let start = NaiveTime::parse_from_str(&captures["start"], &self.time_format).ok()?;
+        let end = match captures.name("end") {
+            Some(end) => Some(NaiveTime::parse_from_str(end.as_str(), &self.time_format).ok()?),
+            None => None,
+        };
+        let offset = match captures.name("offset") {
+            Some(offset) => Some(offset.as_str().parse().ok()?),
+            None => None,
+        };
+        let mut flags = match captures.name("flags") {
+            Some(flags) => RecordingFlags::decode(flags.as_str())?,
+            None => RecordingFlags::default(),
+        };
+        if let Some(stream) = captures.name("stream") {
+            flags.stream = Some(Stream::parse(&stream.as_str().to_ascii_lowercase())?);
+        }
+        if let Some(trigger) = captures.name("trigger") {
+            flags.trigger = Some(Trigger::parse(&trigger.as_str().to_ascii_lowercase())?);
+        }
+
+        Some(ParsedName {
+            camera_name: captures["camera"].to_string(),
+            date,
+            start,
+            end,
+            offset,
+            flags,
+        })
+    }
+}
+
+/// The set of naming schemes configured for an archive, resolved per directory.
+pub struct NamingSchemes {
+    default: Vec<Arc<dyn NamingScheme>>,
+    directories: Vec<(PathBuf, Vec<Arc<dyn NamingScheme>>)>,
+}
+
+impl NamingSchemes {
+    pub fn from_config(config: &NamingConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut available: Vec<Arc<dyn NamingScheme>> = built_in_schemes();
+        for pattern in &config.patterns {
+            available.push(Arc::new(PatternScheme::new(pattern)?));
+        }
+
+        let default = select(&available, &config.scheme)?;
+        let mut directories = Vec::new();
+        for dir in &config.directories {
+            directories.push((PathBuf::from(&dir.path), select(&available, &dir.scheme)?));
+        }
+        // Most specific directory first
+        directories.sort_by_key(|(path, _)| std::cmp::Reverse(path.components().count()));
+
+        Ok(Self {
+            default,
+            directories,
+        })
+    }
+
+    /// Parses a path relative to the archive root with the schemes configured for its directory.
+    pub fn parse(&self, relative: &Path) -> Option<ParsedName> {
+        let schemes = self
+            .directories
+            .iter()
+            .find(|(dir, _)| relative.starts_with(dir))
+            .map(|(_, schemes)| schemes)
+            .unwrap_or(&self.default);
+
+        schemes.iter().find_map(|scheme| scheme.parse(relative))
+    }
+}
+
+fn built_in_schemes() -> Vec<Arc<dyn NamingScheme>> {
+    vec![
+        Arc::new(ReolinkFtp),
+        Arc::new(ReolinkFtpV2),
+        Arc::new(ReolinkSdCard),
+    ]
+}
+
+/// Resolves a scheme name; `auto` tries every built-in scheme in turn.
+fn select(
+    available: &[Arc<dyn NamingScheme>],
+    name: &str,
+) -> Result<Vec<Arc<dyn NamingScheme>>, Box<dyn std::error::Error>> {
+    if name == "auto" {
+        return Ok(built_in_schemes());
+    }
+
+    available
+        .iter()
+        .find(|scheme| scheme.name() == name)
+        .map(|scheme| vec![scheme.clone()])
+        .ok_or_else(|| format!("Unknown naming scheme: {}", name).into())
+}
+
+/// Parse time string in HHMMSS format
+fn parse_hhmmss(time_str: &str) -> Option<NaiveTime> {
+    if time_str.len() != 6 {
+        return None;
+    }
+    NaiveTime::parse_from_str(time_str, "%H%M%S").ok()
+}
+
+fn to_slash_path(path: &Path) -> Option<String> {
+    let parts: Option<Vec<&str>> = path
+        .components()
+        .map(|c| match c {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect();
+    Some(parts?.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn time(h: u32, m: u32, s: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, s).unwrap()
+    }
+
+    /// Parses `path` with `scheme`, keeping the fields every scheme fills in.
+    fn parse(
+        scheme: &dyn NamingScheme,
+        path: &str,
+    ) -> Option<(String, NaiveDate, NaiveTime, Option<NaiveTime>)> {
+        scheme
+            .parse(Path::new(path))
+            .map(|parsed| (parsed.camera_name, parsed.date, parsed.start, parsed.end))
+    }
+
+    #[test]
+    fn reolink_ftp() {
+        let cases = [
+            (
+                "01312024/Front Door-00-083000-083512.mp4",
+                Some((
+                    "Front Door",
+                    date(2024, 1, 31),
+                    time(8, 30, 0),
+                    Some(time(8, 35, 12)),
+                )),
+            ),
+            (
+                "cams/12252023/Garage-00-235959-000105.mp4",
+                Some((
+                    "Garage",
+                    date(2023, 12, 25),
+                    time(23, 59, 59),
+                    Some(time(0, 1, 5)),
+                )),
+            ),
+            ("2024-01-31/Garage-00-083000-083512.mp4", None),
+            ("13312024/Garage-00-083000-083512.mp4", None),
+            ("01312024/Front-Door-00-083000-083512.mp4", None),
+            ("01312024/Garage-00-0830-083512.mp4", None),
+            ("01312024/Garage-00-250000-083512.mp4", None),
+            ("Garage-00-083000-083512.mp4", None),
+        ];
+        for (path, expected) in cases {
+            let expected =
+                expected.map(|(camera, date, start, end)| (camera.to_string(), date, start, end));
+            assert_eq!(parse(&ReolinkFtp, path), expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn reolink_ftp_v2() {
+        let cases = [
+            (
+                "Garage_00_20240131083000.mp4",
+                Some(("Garage", date(2024, 1, 31), time(8, 30, 0))),
+            ),
+            (
+                "uploads/Back_Yard_00_20231225235959.mp4",
+                Some(("Back_Yard", date(2023, 12, 25), time(23, 59, 59))),
+            ),
+            ("Garage_00_2024013108300.mp4", None),
+            ("Garage_000_20240131083000.mp4", None),
+            ("Garage_00_20241331083000.mp4", None),
+            ("00_20240131083000.mp4", None),
+        ];
+        for (path, expected) in cases {
+            let expected =
+                expected.map(|(camera, date, start)| (camera.to_string(), date, start, None));
+            assert_eq!(parse(&ReolinkFtpV2, path), expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn reolink_sdcard() {
+        let cases = [
+            (
+                "Porch/Mp4Record/2024-01-31/RecM01_20240131_083000_083512_6E1C810_4A3F2B.mp4",
+                Some((
+                    "Porch",
+                    date(2024, 1, 31),
+                    time(8, 30, 0),
+                    Some(time(8, 35, 12)),
+                )),
+            ),
+            (
+                "Mp4Record/2024-01-31/RecS02_20240131_083000_083512_0_0.mp4",
+                Some((
+                    "RecS02",
+                    date(2024, 1, 31),
+                    time(8, 30, 0),
+                    Some(time(8, 35, 12)),
+                )),
+            ),
+            (
+                "2024-01-31/RecM01_20240131_083000_083512.mp4",
+                Some((
+                    "RecM01",
+                    date(2024, 1, 31),
+                    time(8, 30, 0),
+                    Some(time(8, 35, 12)),
+                )),
+            ),
+            ("Mp4Record/01312024/RecM01_20240131_083000_083512.mp4", None),
+            ("Mp4Record/2024-01-31/Clip_20240131_083000_083512.mp4", None),
+            ("Mp4Record/2024-01-31/RecM01_20240131_083000.mp4", None),
+            ("RecM01_20240131_083000_083512.mp4", None),
+        ];
+        for (path, expected) in cases {
+            let expected =
+                expected.map(|(camera, date, start, end)| (camera.to_string(), date, start, end));
+            assert_eq!(parse(&ReolinkSdCard, path), expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn ftp_channel_field() {
+        let flags =
+            |scheme: &dyn NamingScheme, path: &str| scheme.parse(Path::new(path)).unwrap().flags;
+        let channel = |channel| RecordingFlags {
+            channel: Some(channel),
+            stream: None,
+            trigger: None,
+        };
+
+        assert_eq!(
+            flags(&ReolinkFtp, "06112023/Front Door-00-103538-104038.mp4"),
+            channel(0)
+        );
+        assert_eq!(
+            flags(&ReolinkFtp, "06112023/NVR-12-103538-104038.mp4"),
+            channel(12)
+        );
+        assert_eq!(
+            flags(&ReolinkFtpV2, "Front Door_00_20230611103538.mp4"),
+            channel(0)
+        );
+        assert_eq!(
+            flags(&ReolinkFtpV2, "NVR_03_20230611103538.mp4"),
+            channel(3)
+        );
+        // Anything but two decimal digits is not a channel
+        assert_eq!(
+            flags(&ReolinkFtpV2, "NVR_4a_20230611103538.mp4"),
+            RecordingFlags::default()
+        );
+    }
+
+    fn pattern(regex: &str) -> PatternConfig {
+        PatternConfig {
+            name: "custom".to_string(),
+            regex: regex.to_string(),
+            date_format: "%Y%m%d".to_string(),
+            time_format: "%H%M%S".to_string(),
+        }
+    }
+
+    #[test]
+    fn pattern_scheme() {
+        let scheme = PatternScheme::new(&pattern(
+            r"^(?P<camera>[^/]+)/(?P<date>\d{8})/(?P<start>\d{6})(?:-(?P<end>\d{6}))?(?:(?P<offset>[+-]\d{2}:\d{2}))?\.mp4$",
+        ))
+        .unwrap();
+
+        assert_eq!(
+            parse(&scheme, "Garage/20240131/083000-083512.mp4"),
+            Some((
+                "Garage".to_string(),
+                date(2024, 1, 31),
+                time(8, 30, 0),
+                Some(time(8, 35, 12))
+            ))
+        );
+        let parsed = scheme
+            .parse(Path::new("Garage/20240131/083000+01:00.mp4"))
+            .unwrap();
+        assert_eq!(parsed.end, None);
+        assert_eq!(parsed.offset, FixedOffset::east_opt(3600));
+
+        for path in [
+            "Garage/2024-01-31/083000.mp4",
+            "Garage/20240131/083000.mkv",
+            "Garage/20241331/083000.mp4",
+            "Garage/20240131/256000.mp4",
+        ] {
+            assert!(scheme.parse(Path::new(path)).is_none(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn pattern_scheme_stream_and_trigger() {
+        let scheme = PatternScheme::new(&pattern(
+            r"^(?P<camera>[^_]+)_(?P<flags>\d{2})_(?P<date>\d{8})(?P<start>\d{6})_(?P<stream>[a-z]+)_(?P<trigger>[A-Za-z]+)\.mp4$",
+        ))
+        .unwrap();
+
+        let parsed = scheme
+            .parse(Path::new("Garage_01_20240131083000_sub_Person.mp4"))
+            .unwrap();
+        assert_eq!(
+            parsed.flags,
+            RecordingFlags {
+                channel: Some(1),
+                stream: Some(Stream::Sub),
+                trigger: Some(Trigger::Person),
+            }
+        );
+        // Values that are not a known stream or trigger do not match at all
+        assert!(scheme
+            .parse(Path::new("Garage_01_20240131083000_sub_doorbell.mp4"))
+            .is_none());
+        assert!(scheme
+            .parse(Path::new("Garage_01_20240131083000_clear_motion.mp4"))
+            .is_none());
+    }
+
+    #[test]
+    fn pattern_scheme_requires_captures() {
+        assert!(PatternScheme::new(&pattern(r"(?P<camera>\w+)/(?P<date>\d{8})")).is_err());
+        assert!(PatternScheme::new(&pattern(r"(?P<camera>[")).is_err());
+    }
+
+    #[test]
+    fn directory_schemes() {
+        let config: NamingConfig = serde_yaml::from_str(
+            "scheme: reolink_ftp\ndirectories:\n  - path: sdcard\n    scheme: reolink_sdcard\n",
+        )
+        .unwrap();
+        let schemes = NamingSchemes::from_config(&config).unwrap();
+
+        let ftp = Path::new("01312024/Garage-00-083000-083512.mp4");
+        assert_eq!(schemes.parse(ftp).unwrap().camera_name, "Garage");
+        let sdcard = Path::new("sdcard/Mp4Record/2024-01-31/RecM01_20240131_083000_083512.mp4");
+        assert_eq!(schemes.parse(sdcard).unwrap().camera_name, "sdcard");
+        assert!(schemes
+            .parse(Path::new("sdcard/01312024/Garage-00-083000-083512.mp4"))
+            .is_none());
+    }
+}
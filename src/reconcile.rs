@@ -0,0 +1,135 @@
+use crate::cli::Config;
+use crate::db::{self, VideoFilter};
+use crate::scanner::{self, SkippedFile};
+use crate::throttle::Throttle;
+use crate::walk::WalkRules;
+use rayon::prelude::*;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Outcome of a reconcile run.
+#[derive(Debug, Default, Serialize)]
+pub struct ReconcileReport {
+    /// Roots whose directory is missing, e.g. an unmounted volume; their videos are left as is.
+    pub skipped_roots: Vec<SkippedRoot>,
+    /// Indexed files no longer on disk, now marked deleted.
+    pub missing: Vec<String>,
+    /// MP4 files on disk that could not be parsed.
+    pub orphans: Vec<SkippedFile>,
+    /// MP4 files on disk that parse but are not indexed yet.
+    pub pending: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SkippedRoot {
+    pub name: String,
+    pub path: String,
+}
+
+impl ReconcileReport {
+    /// Prints the report as human-readable lines.
+    pub fn print_table(&self) {
+        for root in &self.skipped_roots {
+            println!(
+                "Skipped root '{}': {} is missing or not a directory (not mounted?)",
+                root.name, root.path
+            );
+        }
+        for path in &self.missing {
+            println!("Missing: {}", path);
+        }
+        for orphan in &self.orphans {
+            println!("Orphan: {} ({})", orphan.path, orphan.reason);
+        }
+        println!(
+            "{} indexed files missing from disk (marked deleted), {} orphaned files that could not be parsed.",
+            self.missing.len(),
+            self.orphans.len()
+        );
+        if self.pending > 0 {
+            println!(
+                "{} files on disk are not indexed yet; run `reopal import`.",
+                self.pending
+            );
+        }
+    }
+}
+
+/// Brings the index in line with the disk: marks indexed files that no longer exist as
+/// deleted, and lists MP4 files on disk that are not indexed because they failed to parse.
+pub fn run_reconcile(
+    config: &Config,
+    conn: &Connection,
+) -> Result<ReconcileReport, Box<dyn std::error::Error>> {
+    let roots = scanner::load_roots(config)?;
+    let rules = WalkRules::new(&config.scan)?;
+    let recordings = db::query_videos(conn, &VideoFilter::on_disk())?;
+    let mut report = ReconcileReport::default();
+
+    // A root on an unmounted volume would otherwise have its whole archive marked deleted
+    let unavailable: HashSet<&str> = roots
+        .iter()
+        .filter(|root| !root.path.is_dir())
+        .map(|root| root.name.as_str())
+        .collect();
+    for root in roots
+        .iter()
+        .filter(|r| unavailable.contains(r.name.as_str()))
+    {
+        report.skipped_roots.push(SkippedRoot {
+            name: root.name.clone(),
+            path: root.path.display().to_string(),
+        });
+    }
+
+    let tx = db::begin_write(conn)?;
+    for recording in &recordings {
+        let path = Path::new(&recording.file_path);
+        if scanner::root_for(&roots, path).is_some_and(|r| unavailable.contains(r.name.as_str())) {
+            continue;
+        }
+        if !path.exists() {
+            db::mark_as_deleted(&tx, &recording.file_path)?;
+            report.missing.push(recording.file_path.clone());
+        }
+    }
+    tx.commit()?;
+
+    let indexed: HashSet<&str> = recordings.iter().map(|r| r.file_path.as_str()).collect();
+    let mut unindexed: Vec<_> = roots
+        .iter()
+        .filter(|root| !unavailable.contains(root.name.as_str()))
+        .flat_map(|root| rules.find_video_files(&root.path, &root.path))
+        .filter(|path| !path.to_str().is_some_and(|p| indexed.contains(p)))
+        .collect();
+    // Nested roots are walked twice
+    unindexed.sort();
+    unindexed.dedup();
+
+    let throttle = Throttle::new(&config.scan)?;
+    let mut orphans: Vec<_> = throttle.install(|| {
+        unindexed
+            .par_iter()
+            .filter_map(|path| {
+                let root = scanner::root_for(&roots, path)?;
+                throttle.wait();
+                scanner::parse_path(path, root, config)
+                    .err()
+                    .map(|reason| (path, reason))
+            })
+            .collect()
+    });
+    orphans.sort_by(|a, b| a.0.cmp(b.0));
+    report.pending = unindexed.len() - orphans.len();
+    report.orphans = orphans
+        .into_iter()
+        .map(|(path, reason)| SkippedFile {
+            path: path.display().to_string(),
+            reason: reason.to_string(),
+        })
+        .collect();
+
+    Ok(report)
+}
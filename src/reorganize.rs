@@ -0,0 +1,176 @@
+use crate::cli::Config;
+use crate::db::{self, VideoFilter};
+use crate::ingest;
+use crate::layout::Layout;
+use crate::scanner::{self, Root};
+use chrono::NaiveDate;
+use rusqlite::Connection;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Moves indexed recordings into the layout of their root, or `layout` when given, updating
+/// their rows as each batch of moves completes. Every move is journaled before it happens, so
+/// an interrupted run is completed by the next one.
+pub fn run_reorganize(
+    config: &Config,
+    conn: &Connection,
+    layout: Option<&str>,
+    root_name: Option<&str>,
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let roots = scanner::load_roots(config)?;
+    if let Some(name) = root_name {
+        if !roots.iter().any(|root| root.name == name) {
+            return Err(format!("Unknown root: {}", name).into());
+        }
+    }
+    let layout = layout.map(Layout::parse).transpose()?;
+
+    if !dry_run {
+        let journal = db::get_reorganize_journal(conn)?;
+        if !journal.is_empty() {
+            println!(
+                "Completing {} moves from an interrupted run...",
+                journal.len()
+            );
+            finish_moves(conn, &journal, &roots)?;
+        }
+    }
+
+    let recordings = db::query_videos(conn, &VideoFilter::on_disk())?;
+    let mut planned = Vec::new();
+    let mut targets = HashSet::new();
+    let mut conflicts = 0;
+    for root in roots
+        .iter()
+        .filter(|root| root_name.is_none_or(|name| root.name == name))
+    {
+        let layout = layout.as_ref().unwrap_or(&root.layout);
+        for recording in recordings.iter().filter(|r| r.root == root.name) {
+            let from = Path::new(&recording.file_path);
+            let date = NaiveDate::parse_from_str(&recording.date, "%Y-%m-%d")?;
+            let file_name = from.file_name().ok_or("Indexed path has no file name")?;
+            let relative = layout
+                .directory_for(&recording.raw_camera_name, date)
+                .join(file_name);
+
+            // Refuse layouts that would leave recordings unrecognisable to the next import
+            if root.schemes.parse(&relative).is_none() {
+                return Err(format!(
+                    "The layout produces paths that the naming of root '{}' does not recognise, e.g. {}",
+                    root.name,
+                    relative.display()
+                )
+                .into());
+            }
+
+            let to = root.path.join(&relative);
+            if to == from {
+                continue;
+            }
+            if to.exists() || !targets.insert(to.clone()) {
+                println!(
+                    "Conflict: {} -> {} (target taken)",
+                    from.display(),
+                    to.display()
+                );
+                conflicts += 1;
+                continue;
+            }
+            planned.push((
+                recording.file_path.clone(),
+                to.to_string_lossy().into_owned(),
+            ));
+        }
+    }
+
+    if dry_run {
+        for (from, to) in &planned {
+            println!("Would move: {} -> {}", from, to);
+        }
+        println!(
+            "{} files would be moved, {} conflicts.",
+            planned.len(),
+            conflicts
+        );
+        return Ok(());
+    }
+
+    let mut moved = 0;
+    for chunk in planned.chunks(config.scan.batch_size.max(1)) {
+        let tx = db::begin_write(conn)?;
+        for (from, to) in chunk {
+            db::add_journal_entry(&tx, from, to)?;
+        }
+        tx.commit()?;
+
+        moved += finish_moves(conn, chunk, &roots)?;
+        eprintln!("Moved {} of {} files", moved, planned.len());
+    }
+
+    println!(
+        "{} files moved, {} failed, {} conflicts.",
+        moved,
+        planned.len() - moved,
+        conflicts
+    );
+    Ok(())
+}
+
+/// Carries out journaled moves, then points the moved rows at their new paths and clears the
+/// journal entries in one transaction. Moves completed before an interruption are recognised
+/// and only recorded.
+fn finish_moves(
+    conn: &Connection,
+    moves: &[(String, String)],
+    roots: &[Root],
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut done = Vec::new();
+    for (from, to) in moves {
+        let (source, target) = (Path::new(from), Path::new(to));
+        let result = match (source.exists(), target.exists()) {
+            (false, true) => Ok(()),
+            // Linked into place, but the source was not yet removed
+            (true, true) if ingest::same_contents(source, target).unwrap_or(false) => {
+                fs::remove_file(source)
+            }
+            _ => ingest::move_file(source, target),
+        };
+
+        match result {
+            Ok(()) => {
+                remove_empty_parents(source, roots);
+                done.push((from, to));
+            }
+            Err(e) => println!("Failed: {} ({})", from, e),
+        }
+    }
+
+    let tx = db::begin_write(conn)?;
+    for (from, to) in &done {
+        db::rename_video(&tx, from, to)?;
+    }
+    for (from, _) in moves {
+        db::remove_journal_entry(&tx, from)?;
+    }
+    tx.commit()?;
+    Ok(done.len())
+}
+
+/// Removes the directories a file was moved out of, up to its root, once they are empty.
+fn remove_empty_parents(path: &Path, roots: &[Root]) {
+    let Some(root) = scanner::root_for(roots, path) else {
+        return;
+    };
+    let mut dir: Option<PathBuf> = path.parent().map(Path::to_path_buf);
+    while let Some(current) = dir {
+        if current == root.path
+            || !current.starts_with(&root.path)
+            || fs::remove_dir(&current).is_err()
+        {
+            break;
+        }
+        dir = current.parent().map(Path::to_path_buf);
+    }
+}
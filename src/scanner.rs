@@ -1,112 +1,1015 @@
-use crate::db;
-use crate::models::VideoRecording;
-use chrono::{DateTime, TimeZone, Utc};
-use chrono_tz::US::Eastern;
+use crate::cli::{Config, DstPolicy, IoPriority, ScanConfig};
+use crate::db::{self, DirectoryWatermark, UpsertOutcome, VideoFilter};
+use crate::ingest;
+use crate::layout::Layout;
+use crate::models::{MediaInfo, VideoHealth, VideoRecording};
+use crate::mp4::{self, Mp4Info};
+use crate::naming::NamingSchemes;
+use crate::throttle::{self, Throttle};
+use crate::timestamps;
+use crate::walk::{self, WalkRules};
+use chrono::{DateTime, Utc};
 use rayon::prelude::*;
-use rusqlite::{Connection, Result};
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
 use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
+use std::sync::mpsc::{self, SyncSender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-/// Scans the given directory in parallel, parses file information, and inserts it into the database.
-pub fn scan_directory(dir_path: &str, conn: &Connection) -> Result<()> {
-    let paths: Vec<PathBuf> = WalkDir::new(dir_path)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .map(|e| e.into_path())
-        .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "mp4"))
-        .collect();
+/// Outcome of a scan, printed by the CLI and returned by the import API.
+#[derive(Debug, Default, Serialize)]
+pub struct ScanReport {
+    pub files_seen: usize,
+    pub added: usize,
+    pub updated: usize,
+    pub resurrected: usize,
+    pub unchanged: usize,
+    /// Directories skipped because they match their stored watermark.
+    pub unchanged_directories: usize,
+    /// Files that may still be being written, left for the next scan.
+    pub deferred: Vec<String>,
+    pub skipped: Vec<SkippedFile>,
+    /// Damaged files moved into `scan.quarantine_dir`.
+    pub quarantined: Vec<QuarantinedFile>,
+    /// Added or changed videos that are truncated or unreadable.
+    pub unhealthy: usize,
+    /// Added or changed videos whose local time needed the DST policy.
+    pub dst_flagged: usize,
+    pub errors: Vec<String>,
+    pub elapsed_seconds: f64,
+    pub cameras: BTreeMap<String, CameraCounts>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CameraCounts {
+    pub seen: usize,
+    pub added: usize,
+    pub updated: usize,
+    pub resurrected: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuarantinedFile {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SkippedFile {
+    pub path: String,
+    pub reason: String,
+}
+
+/// Why a file could not be turned into a VideoRecording.
+#[derive(Debug)]
+pub enum SkipReason {
+    NotUtf8,
+    OutsideRoot,
+    UnrecognizedName,
+    Metadata(std::io::Error),
+    NoTimestamp,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::NotUtf8 => write!(f, "path is not valid UTF-8"),
+            SkipReason::OutsideRoot => write!(f, "path is outside every archive root"),
+            SkipReason::UnrecognizedName => {
+                write!(f, "file name does not match any naming scheme")
+            }
+            SkipReason::Metadata(e) => write!(f, "cannot read file metadata: {}", e),
+            SkipReason::NoTimestamp => {
+                write!(f, "no configured timestamp source produced a time")
+            }
+        }
+    }
+}
+
+impl ScanReport {
+    /// Prints the report as human-readable tables.
+    pub fn print_table(&self, policy: DstPolicy) {
+        println!("{:<14} {:>8}", "Files seen", self.files_seen);
+        println!("{:<14} {:>8}", "Added", self.added);
+        println!("{:<14} {:>8}", "Updated", self.updated);
+        println!("{:<14} {:>8}", "Resurrected", self.resurrected);
+        println!("{:<14} {:>8}", "Unchanged", self.unchanged);
+        println!("{:<14} {:>8}", "Unchanged dirs", self.unchanged_directories);
+        println!("{:<14} {:>8}", "Deferred", self.deferred.len());
+        println!("{:<14} {:>8}", "Skipped", self.skipped.len());
+        println!("{:<14} {:>8}", "Quarantined", self.quarantined.len());
+        println!("{:<14} {:>8}", "Errors", self.errors.len());
+        println!("{:<14} {:>7.2}s", "Elapsed", self.elapsed_seconds);
+
+        if !self.cameras.is_empty() {
+            println!();
+            println!(
+                "{:<20} {:>8} {:>8} {:>8} {:>12}",
+                "Camera", "Seen", "Added", "Updated", "Resurrected"
+            );
+            for (camera, counts) in &self.cameras {
+                println!(
+                    "{:<20} {:>8} {:>8} {:>8} {:>12}",
+                    camera, counts.seen, counts.added, counts.updated, counts.resurrected
+                );
+            }
+        }
+
+        if !self.skipped.is_empty() {
+            println!();
+            println!("Skipped files:");
+            for skipped in &self.skipped {
+                println!("  {}: {}", skipped.path, skipped.reason);
+            }
+        }
+        if !self.quarantined.is_empty() {
+            println!();
+            println!("Quarantined files:");
+            for quarantined in &self.quarantined {
+                println!("  {} -> {}", quarantined.from, quarantined.to);
+            }
+        }
+        if !self.errors.is_empty() {
+            println!();
+            println!("Errors:");
+            for error in &self.errors {
+                println!("  {}", error);
+            }
+        }
+
+        report_flagged(self.dst_flagged, policy);
+        if self.unhealthy > 0 {
+            println!(
+                "{} videos are truncated or unreadable (see `reopal health`).",
+                self.unhealthy
+            );
+        }
+    }
+}
+
+/// A run of files from one directory, passed from the walker to the indexer.
+struct Batch {
+    directory: PathBuf,
+    files: Vec<PathBuf>,
+    /// Set on the last batch of a directory, once it can be checkpointed.
+    completes_directory: bool,
+    /// Watermark to store once the directory has been committed.
+    watermark: Option<DirectoryWatermark>,
+}
+
+/// What the walker reports to the indexer.
+enum WalkEvent {
+    Batch(Batch),
+    /// A directory matching its watermark, whose files were not sent.
+    Unchanged,
+    Error(String),
+}
+
+/// Number of batches the walker may queue ahead of the indexer.
+const BATCH_QUEUE_DEPTH: usize = 2;
+
+/// An archive root with its naming schemes resolved.
+pub struct Root {
+    pub name: String,
+    pub path: PathBuf,
+    pub schemes: NamingSchemes,
+    pub layout: Layout,
+}
+
+/// Resolves the configured roots, each using its own naming and layout or the top-level ones.
+pub fn load_roots(config: &Config) -> Result<Vec<Root>, Box<dyn std::error::Error>> {
+    let mut roots: Vec<Root> = Vec::new();
+    for root in config.roots() {
+        if roots.iter().any(|r| r.name == root.name) {
+            return Err(format!("Duplicate root name: {}", root.name).into());
+        }
+        // An empty path would contain every path, and walks would fail obscurely
+        if root.path.is_empty() {
+            return Err(if config.roots.is_empty() {
+                "No archive configured: set `directory` or `roots` in the config".to_string()
+            } else {
+                format!("Root '{}' has an empty `path`", root.name)
+            }
+            .into());
+        }
+        let naming = root.naming.as_ref().unwrap_or(&config.naming);
+        // Quarantined files would be walked, and quarantined again, on every scan
+        if let Some(quarantine_dir) = &config.scan.quarantine_dir {
+            if Path::new(quarantine_dir).starts_with(&root.path) {
+                return Err(format!(
+                    "scan.quarantine_dir '{}' is inside root '{}'; move it outside the archive",
+                    quarantine_dir, root.name
+                )
+                .into());
+            }
+        }
+        roots.push(Root {
+            schemes: NamingSchemes::from_config(naming)?,
+            layout: Layout::parse(root.layout.as_deref().unwrap_or(&config.layout))?,
+            name: root.name,
+            path: PathBuf::from(root.path),
+        });
+    }
+    Ok(roots)
+}
+
+/// Finds the root containing `path`, preferring the most specific when roots are nested.
+pub fn root_for<'a>(roots: &'a [Root], path: &Path) -> Option<&'a Root> {
+    roots
+        .iter()
+        .filter(|root| path.starts_with(&root.path))
+        .max_by_key(|root| root.path.components().count())
+}
+
+/// Scans every configured root, parsing files in parallel and committing them to the database
+/// in bounded batches. An interrupted scan resumes after the last directory it committed.
+///
+/// Directories whose modification time and file count match the watermark stored by an earlier
+/// scan are skipped, unless `full` is set.
+pub fn scan_directory(
+    config: &Config,
+    conn: &Connection,
+    full: bool,
+) -> Result<ScanReport, Box<dyn std::error::Error>> {
+    let started = Instant::now();
+    let roots = load_roots(config)?;
+    let rules = WalkRules::new(&config.scan)?;
+    let indexer = Indexer::new(&roots, config)?;
+    let mut report = ScanReport::default();
+
+    let watermarks = if full {
+        HashMap::new()
+    } else {
+        db::get_directory_watermarks(conn)?
+    };
+
+    for root in &roots {
+        let checkpoint = db::get_scan_checkpoint(conn, &root.name)?.map(PathBuf::from);
+        if let Some(directory) = &checkpoint {
+            eprintln!("Resuming interrupted scan after {}", directory.display());
+        }
+
+        // Roots nested inside this one are walked on their own
+        let nested: Vec<&Path> = roots
+            .iter()
+            .filter(|other| other.path != root.path && other.path.starts_with(&root.path))
+            .map(|other| other.path.as_path())
+            .collect();
+
+        let (sender, receiver) = mpsc::sync_channel(BATCH_QUEUE_DEPTH);
+        let walker = Walker {
+            root: &root.path,
+            rules: &rules,
+            io_priority: config.scan.io_priority,
+            nested: &nested,
+            resume_after: checkpoint.as_deref(),
+            batch_size: config.scan.batch_size.max(1),
+            watermarks: &watermarks,
+            started: Utc::now(),
+            sender,
+        };
+
+        thread::scope(|scope| {
+            scope.spawn(move || walker.run());
+
+            let mut directory_deferred = false;
+            for event in receiver {
+                match event {
+                    WalkEvent::Batch(batch) => index_batch(
+                        batch,
+                        root,
+                        &indexer,
+                        conn,
+                        &mut directory_deferred,
+                        &mut report,
+                    )?,
+                    WalkEvent::Unchanged => report.unchanged_directories += 1,
+                    WalkEvent::Error(e) => report.errors.push(e),
+                }
+            }
+            Ok::<(), Box<dyn std::error::Error>>(())
+        })?;
+
+        // The walk completed, so the next scan starts from the beginning
+        db::clear_scan_checkpoint(conn, &root.name)?;
+    }
+
+    report.elapsed_seconds = started.elapsed().as_secs_f64();
+    Ok(report)
+}
+
+/// Walks `root` depth-first with files ahead of subdirectories, so each directory's files are
+/// contiguous and directories arrive in path order.
+struct Walker<'a> {
+    root: &'a Path,
+    rules: &'a WalkRules,
+    io_priority: IoPriority,
+    /// Other roots inside this one, which are not descended into.
+    nested: &'a [&'a Path],
+    /// Directories up to and including this one were committed by an earlier scan.
+    resume_after: Option<&'a Path>,
+    batch_size: usize,
+    watermarks: &'a HashMap<String, DirectoryWatermark>,
+    started: DateTime<Utc>,
+    sender: SyncSender<WalkEvent>,
+}
+
+/// The directory whose files the walker is currently collecting.
+struct DirectoryWalk {
+    directory: PathBuf,
+    mtime: Option<DateTime<Utc>>,
+    /// File count of the stored watermark, if its modification time still matches. Files are
+    /// held back until the count confirms that the directory is unchanged.
+    watermark_count: Option<usize>,
+    files: Vec<PathBuf>,
+    file_count: usize,
+}
+
+impl Walker<'_> {
+    fn run(self) {
+        // Failures are reported by the indexer's thread pool
+        let _ = throttle::set_io_priority(self.io_priority);
+        let walker = self
+            .rules
+            .walk_dir(self.root)
+            .sort_by(|a, b| {
+                a.file_type()
+                    .is_dir()
+                    .cmp(&b.file_type().is_dir())
+                    .then_with(|| a.file_name().cmp(b.file_name()))
+            })
+            .into_iter()
+            .filter_entry(|entry| {
+                let is_dir = entry.file_type().is_dir();
+                if self.nested.contains(&entry.path())
+                    || !self.rules.allows(self.root, entry.path(), is_dir)
+                {
+                    return false;
+                }
+                match self.resume_after {
+                    // Keep ancestors of the checkpoint, whose later subdirectories are still pending
+                    Some(done) if is_dir => entry.path() > done || done.starts_with(entry.path()),
+                    _ => true,
+                }
+            });
+
+        let mut current: Option<DirectoryWalk> = None;
+        for entry in walker {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    if !self.send(WalkEvent::Error(walk::describe_error(&e))) {
+                        return;
+                    }
+                    continue;
+                }
+            };
+
+            // The entry type comes from the directory listing; only symlinks need a stat
+            let file_type = entry.file_type();
+            let is_file = file_type.is_file() || (file_type.is_symlink() && entry.path().is_file());
+            let path = entry.into_path();
+            if !is_file || path.extension().is_none_or(|ext| ext != "mp4") {
+                continue;
+            }
+            let directory = path.parent().unwrap_or(self.root).to_path_buf();
+            if self
+                .resume_after
+                .is_some_and(|done| directory.as_path() <= done)
+            {
+                continue;
+            }
+
+            let walk = match current.take() {
+                Some(walk) if walk.directory == directory => walk,
+                finished => {
+                    if let Some(finished) = finished {
+                        if !self.finish(finished) {
+                            return;
+                        }
+                    }
+                    self.enter(directory)
+                }
+            };
+            let walk = current.insert(walk);
+            walk.file_count += 1;
+            walk.files.push(path);
+
+            if walk.watermark_count.is_none() && walk.files.len() >= self.batch_size {
+                let batch = Batch {
+                    directory: walk.directory.clone(),
+                    files: std::mem::take(&mut walk.files),
+                    completes_directory: false,
+                    watermark: None,
+                };
+                if !self.send(WalkEvent::Batch(batch)) {
+                    return;
+                }
+            }
+        }
+
+        if let Some(finished) = current {
+            self.finish(finished);
+        }
+    }
+
+    fn enter(&self, directory: PathBuf) -> DirectoryWalk {
+        let mtime = fs::metadata(&directory)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .map(DateTime::<Utc>::from);
+        let watermark_count = self
+            .watermarks
+            .get(&*directory.to_string_lossy())
+            .filter(|watermark| Some(watermark.mtime) == mtime)
+            .map(|watermark| watermark.file_count);
+
+        DirectoryWalk {
+            directory,
+            mtime,
+            watermark_count,
+            files: Vec::new(),
+            file_count: 0,
+        }
+    }
+
+    /// Sends the remaining files of a directory, or reports it unchanged. Returns false once
+    /// the indexer has gone away.
+    fn finish(&self, walk: DirectoryWalk) -> bool {
+        if walk.watermark_count == Some(walk.file_count) {
+            return self.send(WalkEvent::Unchanged);
+        }
+
+        // A directory modified after the scan started may have gained files the walk missed
+        let watermark = walk
+            .mtime
+            .filter(|mtime| *mtime < self.started)
+            .map(|mtime| DirectoryWatermark {
+                mtime,
+                file_count: walk.file_count,
+            });
+
+        let mut files = walk.files;
+        while files.len() > self.batch_size {
+            let rest = files.split_off(self.batch_size);
+            let batch = Batch {
+                directory: walk.directory.clone(),
+                files: std::mem::replace(&mut files, rest),
+                completes_directory: false,
+                watermark: None,
+            };
+            if !self.send(WalkEvent::Batch(batch)) {
+                return false;
+            }
+        }
+        self.send(WalkEvent::Batch(Batch {
+            directory: walk.directory,
+            files,
+            completes_directory: true,
+            watermark,
+        }))
+    }
 
-    let records: Vec<VideoRecording> = paths
-        .par_iter()
-        .filter_map(|path| parse_path(path))
-        .collect();
+    fn send(&self, event: WalkEvent) -> bool {
+        self.sender.send(event).is_ok()
+    }
+}
 
-    let tx = conn.unchecked_transaction()?;
-    for record in records {
-        let changes = db::insert_record(&tx, &record)?;
-        if changes > 0 {
-            println!("Added: {}", record.file_path);
+/// Parses a batch in parallel and commits it, checkpointing its directory when complete.
+/// `directory_deferred` tracks whether any file of the current directory was deferred, in
+/// which case no watermark is stored so that the next scan revisits it.
+fn index_batch(
+    batch: Batch,
+    root: &Root,
+    indexer: &Indexer,
+    conn: &Connection,
+    directory_deferred: &mut bool,
+    report: &mut ScanReport,
+) -> Result<(), Box<dyn std::error::Error>> {
+    report.files_seen += batch.files.len();
+    let (paths, deferred) = indexer
+        .throttle
+        .install(|| split_unstable(batch.files, &indexer.config.scan));
+    *directory_deferred |= !deferred.is_empty();
+    report
+        .deferred
+        .extend(deferred.iter().map(|path| path.display().to_string()));
+
+    let tx = db::begin_write(conn)?;
+    indexer.index_files(&paths, &tx, report)?;
+    if batch.completes_directory {
+        let directory = batch.directory.to_string_lossy();
+        db::set_scan_checkpoint(&tx, &root.name, &directory)?;
+        if let Some(watermark) = batch.watermark.filter(|_| !*directory_deferred) {
+            db::set_directory_watermark(&tx, &directory, &watermark)?;
         }
+        *directory_deferred = false;
     }
     tx.commit()?;
 
+    eprintln!(
+        "Scanned {} files ({} added, {} updated, {} resurrected) - {}",
+        report.files_seen,
+        report.added,
+        report.updated,
+        report.resurrected,
+        batch.directory.display()
+    );
     Ok(())
 }
 
-/// Parses the file path to extract video metadata.
-fn parse_path(path: &Path) -> Option<VideoRecording> {
-    let file_name = path.file_stem()?.to_str()?;
-    let file_path = path.to_str()?;
-    let parent_dir = path.parent()?.file_name()?.to_str()?;
+/// A file and the recording parsed from it, or why it was skipped.
+pub type ParsedFile<'p> = (&'p PathBuf, Result<VideoRecording, SkipReason>);
+
+/// Parses and upserts files for a scan or watch session, sharing its roots and throttling
+/// across batches.
+pub struct Indexer<'a> {
+    roots: &'a [Root],
+    config: &'a Config,
+    throttle: Throttle,
+}
 
-    // Validate directory name format (MMDDYYYY)
-    if parent_dir.len() != 8 || !parent_dir.chars().all(|c| c.is_ascii_digit()) {
-        return None;
+impl<'a> Indexer<'a> {
+    pub fn new(roots: &'a [Root], config: &'a Config) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            roots,
+            config,
+            throttle: Throttle::new(&config.scan)?,
+        })
     }
-    let date = parent_dir.to_string();
 
-    // Parse filename format: <camera name>-00-<start time>-<end time>
-    let parts: Vec<&str> = file_name.split('-').collect();
-    if parts.len() != 4 {
-        return None;
+    /// Parses files in parallel with the schemes of the root containing them and upserts them,
+    /// quarantining unhealthy ones when configured. Outcomes are tallied in `report`.
+    pub fn index_files(
+        &self,
+        paths: &[PathBuf],
+        conn: &Connection,
+        report: &mut ScanReport,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.store_files(self.parse_files(paths), conn, report)
     }
 
-    let camera_name = parts[0].to_string();
-    let filename_start_time = parts[2];
-    let filename_end_time = parts[3];
+    /// Parses files in parallel with the schemes of the root containing them, under the
+    /// throttle. Touches no database, so callers sharing a connection need not hold it.
+    pub fn parse_files<'p>(&self, paths: &'p [PathBuf]) -> Vec<ParsedFile<'p>> {
+        self.throttle.install(|| {
+            paths
+                .par_iter()
+                .map(|path| (path, self.parse(path, path)))
+                .collect()
+        })
+    }
 
-    if filename_start_time.len() != 6 || filename_end_time.len() != 6 {
-        return None;
+    /// Parses files about to be moved, given as source and target pairs, for their targets.
+    /// Each is read at its source, as a copy across filesystems gets new file times.
+    pub fn parse_moves<'p>(&self, moves: &'p [(PathBuf, PathBuf)]) -> Vec<ParsedFile<'p>> {
+        self.throttle.install(|| {
+            moves
+                .par_iter()
+                .map(|(source, target)| (target, self.parse(source, target)))
+                .collect()
+        })
     }
 
-    // Calculate duration from filename times
-    let start_seconds = parse_time_to_seconds(filename_start_time)?;
-    let end_seconds = parse_time_to_seconds(filename_end_time)?;
-    let duration_seconds = if end_seconds >= start_seconds {
-        end_seconds - start_seconds
-    } else {
-        // Handle case where end time is past midnight (next day)
-        (24 * 3600 - start_seconds) + end_seconds
-    };
+    fn parse(&self, source: &Path, path: &Path) -> Result<VideoRecording, SkipReason> {
+        self.throttle.wait();
+        match root_for(self.roots, path) {
+            Some(root) => parse_moved_path(source, path, root, self.config),
+            None => Err(SkipReason::OutsideRoot),
+        }
+    }
+
+    /// Upserts files returned by [`Indexer::parse_files`], quarantining unhealthy ones when
+    /// configured. Outcomes are tallied in `report`.
+    pub fn store_files(
+        &self,
+        parsed: Vec<ParsedFile>,
+        conn: &Connection,
+        report: &mut ScanReport,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for (path, result) in parsed {
+            let mut record = match result {
+                Ok(record) => record,
+                Err(reason) => {
+                    report.skipped.push(SkippedFile {
+                        path: path.display().to_string(),
+                        reason: reason.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            if record.health != VideoHealth::Ok {
+                if let Some(quarantine_dir) = &self.config.scan.quarantine_dir {
+                    match quarantine(&record.file_path, self.roots, quarantine_dir) {
+                        Ok(target) => {
+                            report.quarantined.push(QuarantinedFile {
+                                from: std::mem::replace(&mut record.file_path, target.clone()),
+                                to: target,
+                            });
+                        }
+                        Err(e) => report
+                            .errors
+                            .push(format!("Failed to quarantine {}: {}", record.file_path, e)),
+                    }
+                }
+            }
+
+            let camera = report
+                .cameras
+                .entry(record.camera_name.clone())
+                .or_default();
+            camera.seen += 1;
+            match db::upsert_record(conn, &record)? {
+                UpsertOutcome::Added => {
+                    report.added += 1;
+                    camera.added += 1;
+                }
+                UpsertOutcome::Updated => {
+                    report.updated += 1;
+                    camera.updated += 1;
+                }
+                UpsertOutcome::Resurrected => {
+                    report.resurrected += 1;
+                    camera.resurrected += 1;
+                }
+                UpsertOutcome::Unchanged => {
+                    report.unchanged += 1;
+                    continue;
+                }
+            }
+
+            if record.time_flag.is_some() {
+                report.dst_flagged += 1;
+            }
+            if record.health != VideoHealth::Ok {
+                report.unhealthy += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Separates files that may still be being written from those safe to index. Files modified
+/// within `min_age_seconds` are re-checked after `settle_seconds` and kept only if unchanged.
+pub fn split_unstable(paths: Vec<PathBuf>, scan: &ScanConfig) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    if scan.min_age_seconds == 0 {
+        return (paths, Vec::new());
+    }
+
+    let min_age = Duration::from_secs(scan.min_age_seconds);
+    let (recent, stable): (Vec<_>, Vec<_>) = paths
+        .into_par_iter()
+        .map(|path| {
+            let snapshot = file_snapshot(&path);
+            (path, snapshot)
+        })
+        .partition(|(_, snapshot)| match snapshot {
+            Some((_, modified)) => modified.elapsed().map_or(true, |age| age < min_age),
+            None => true,
+        });
+
+    let mut stable: Vec<PathBuf> = stable.into_iter().map(|(path, _)| path).collect();
+    if recent.is_empty() || scan.settle_seconds == 0 {
+        return (stable, recent.into_iter().map(|(path, _)| path).collect());
+    }
+
+    thread::sleep(Duration::from_secs(scan.settle_seconds));
 
-    let metadata = std::fs::metadata(path).ok()?;
+    let mut deferred = Vec::new();
+    for (path, before) in recent {
+        if before.is_some() && file_snapshot(&path) == before {
+            stable.push(path);
+        } else {
+            deferred.push(path);
+        }
+    }
+    (stable, deferred)
+}
+
+/// Size and modification time of a file, used to detect ongoing writes.
+fn file_snapshot(path: &Path) -> Option<(u64, SystemTime)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+/// Moves a damaged recording into the quarantine directory, keeping its path relative to its root.
+fn quarantine(
+    file_path: &str,
+    roots: &[Root],
+    quarantine_dir: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let source = Path::new(file_path);
+    let root = root_for(roots, source).ok_or("file is outside every root")?;
+    let relative = source.strip_prefix(&root.path)?;
+    let target = Path::new(quarantine_dir).join(relative);
+
+    // Never replaces a file quarantined earlier under the same path
+    ingest::move_file(source, &target)?;
+    Ok(target.to_string_lossy().into_owned())
+}
+
+fn report_flagged(flagged: usize, policy: DstPolicy) {
+    if flagged > 0 {
+        println!(
+            "{} videos had ambiguous or nonexistent local times (resolved with {:?} policy).",
+            flagged, policy
+        );
+    }
+}
+
+/// Moves indexed videos to the camera their raw name maps to under the current camera aliases,
+/// returning the number of videos renamed.
+pub fn apply_camera_aliases(
+    config: &Config,
+    conn: &Connection,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut owners: HashMap<&str, &str> = HashMap::new();
+    for (name, camera) in &config.cameras {
+        for alias in &camera.aliases {
+            if let Some(other) = owners.insert(alias, name) {
+                return Err(format!(
+                    "camera alias '{}' is listed under both '{}' and '{}'",
+                    alias, other, name
+                )
+                .into());
+            }
+        }
+    }
+
+    let tx = db::begin_write(conn)?;
+    let mut renamed = 0;
+    for raw_name in db::get_raw_camera_names(&tx)? {
+        renamed += db::rename_camera(&tx, &raw_name, config.camera_name_for(&raw_name))?;
+    }
+    tx.commit()?;
+    Ok(renamed)
+}
+
+/// Re-derives start and end times of indexed videos from their files using the current timezone settings.
+pub fn retime(config: &Config, conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    let roots = load_roots(config)?;
+    let recordings = db::query_videos(conn, &VideoFilter::on_disk())?;
+
+    let mut updated = 0;
+    let mut unavailable = 0;
+    let mut flagged = 0;
+    let tx = db::begin_write(conn)?;
+    for recording in &recordings {
+        let path = Path::new(&recording.file_path);
+        let result = match root_for(&roots, path) {
+            Some(root) => parse_path(path, root, config),
+            None => Err(SkipReason::OutsideRoot),
+        };
+        match result {
+            Ok(parsed) => {
+                if parsed.time_flag.is_some() {
+                    flagged += 1;
+                }
+                if parsed.start_time != recording.start_time
+                    || parsed.end_time != recording.end_time
+                    || parsed.time_flag != recording.time_flag
+                    || parsed.time_source != recording.time_source
+                    || parsed.flags != recording.flags
+                {
+                    db::update_times(&tx, &parsed)?;
+                    updated += 1;
+                }
+            }
+            Err(reason) => {
+                println!("Skipped: {}: {}", recording.file_path, reason);
+                unavailable += 1;
+            }
+        }
+    }
+    tx.commit()?;
+
+    println!(
+        "Checked {} videos: {} updated, {} skipped.",
+        recordings.len(),
+        updated,
+        unavailable
+    );
+    report_flagged(flagged, config.scan.dst_policy);
+    Ok(())
+}
+
+/// Parses the file path with the naming schemes of its root to extract video metadata.
+pub fn parse_path(path: &Path, root: &Root, config: &Config) -> Result<VideoRecording, SkipReason> {
+    parse_moved_path(path, path, root, config)
+}
+
+/// Parses `path` like [`parse_path`], reading the file itself at `source`, where it is until
+/// it is moved to `path`.
+pub fn parse_moved_path(
+    source: &Path,
+    path: &Path,
+    root: &Root,
+    config: &Config,
+) -> Result<VideoRecording, SkipReason> {
+    let file_path = path.to_str().ok_or(SkipReason::NotUtf8)?;
+    let relative = path
+        .strip_prefix(&root.path)
+        .map_err(|_| SkipReason::OutsideRoot)?;
+    let parsed = root
+        .schemes
+        .parse(relative)
+        .ok_or(SkipReason::UnrecognizedName)?;
+
+    let metadata = fs::metadata(source).map_err(SkipReason::Metadata)?;
     let file_size = metadata.len();
+    let (health, mp4_info) = mp4::probe(source);
+    let times = timestamps::resolve_times(&metadata, mp4_info.as_ref(), &parsed, config)
+        .ok_or(SkipReason::NoTimestamp)?;
 
-    // Use file creation time as end_time
-    let created_time = metadata.created().ok()?;
-    // Convert SystemTime to DateTime<Utc>, then treat as Eastern time and convert to UTC
-    let utc_time: DateTime<Utc> = created_time.into();
-    let naive_time = utc_time.naive_utc();
-    let eastern_time = Eastern.from_local_datetime(&naive_time).single()?;
-    let end_time = eastern_time.with_timezone(&Utc);
-
-    // Calculate start_time as end_time minus duration
-    let start_time = end_time - chrono::Duration::seconds(duration_seconds as i64);
-
-    Some(VideoRecording {
-        camera_name,
-        date,
-        start_time,
-        end_time,
+    Ok(VideoRecording {
+        id: None,
+        camera_name: config.camera_name_for(&parsed.camera_name).to_string(),
+        raw_camera_name: parsed.camera_name,
+        date: parsed.date.format("%Y-%m-%d").to_string(),
+        start_time: times.start_time,
+        end_time: times.end_time,
         file_path: file_path.to_string(),
+        root: root.name.clone(),
         file_size,
+        file_mtime: metadata.modified().ok().map(DateTime::<Utc>::from),
         deleted: false,
+        time_flag: times.time_flag,
+        time_source: times.source,
+        media: media_info(mp4_info.as_ref(), file_size),
+        health,
+        flags: parsed.flags,
     })
 }
 
-/// Parse time string in HHMMSS format to seconds since midnight
-fn parse_time_to_seconds(time_str: &str) -> Option<u32> {
-    if time_str.len() != 6 {
-        return None;
+fn media_info(mp4_info: Option<&Mp4Info>, file_size: u64) -> MediaInfo {
+    let Some(info) = mp4_info else {
+        return MediaInfo::default();
+    };
+
+    let duration = info.duration_seconds().filter(|seconds| *seconds > 0.0);
+    let video = info.video.as_ref();
+    MediaInfo {
+        duration,
+        width: video.map(|v| v.width).filter(|w| *w > 0),
+        height: video.map(|v| v.height).filter(|h| *h > 0),
+        codec: video.map(|v| v.codec()).filter(|c| !c.is_empty()),
+        frame_rate: video.and_then(|v| v.frame_rate),
+        bitrate: duration.map(|seconds| (file_size as f64 * 8.0 / seconds) as u64),
     }
+}
 
-    let hours: u32 = time_str[0..2].parse().ok()?;
-    let minutes: u32 = time_str[2..4].parse().ok()?;
-    let seconds: u32 = time_str[4..6].parse().ok()?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations;
 
-    if hours >= 24 || minutes >= 60 || seconds >= 60 {
-        return None;
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("reopal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
     }
 
-    Some(hours * 3600 + minutes * 60 + seconds)
+    /// Creates empty recordings at `paths`, relative to `root`; their times come from the names.
+    fn create_files(root: &Path, paths: &[&str]) {
+        for path in paths {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+        }
+    }
+
+    fn test_config(root: &Path, scan: &str) -> Config {
+        serde_yaml::from_str(&format!(
+            "directory: {}\ndb_path: ':memory:'\nscan:\n  timestamp_sources: [filename]\n{}",
+            root.display(),
+            scan
+        ))
+        .unwrap()
+    }
+
+    fn indexed_paths(conn: &Connection) -> Vec<String> {
+        let mut paths: Vec<String> = db::query_videos(conn, &VideoFilter::on_disk())
+            .unwrap()
+            .into_iter()
+            .map(|recording| recording.file_path)
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn interrupted_scan_resumes_after_checkpoint() {
+        let root = scratch_dir("resume");
+        create_files(
+            &root,
+            &[
+                "a/01312024/Garage-00-080000-080500.mp4",
+                "b/01312024/Garage-00-090000-090500.mp4",
+                "b/02012024/Garage-00-100000-100500.mp4",
+                "c/01312024/Porch-00-110000-110500.mp4",
+            ],
+        );
+        let config = test_config(&root, "  min_age_seconds: 0");
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&conn).unwrap();
+        let checkpoint = root.join("b/01312024");
+        db::set_scan_checkpoint(&conn, db::DEFAULT_ROOT, &checkpoint.to_string_lossy()).unwrap();
+
+        let report = scan_directory(&config, &conn, false).unwrap();
+        assert_eq!(report.files_seen, 2);
+        // Later subdirectories of the checkpoint's parent and of the root are still walked
+        assert_eq!(
+            indexed_paths(&conn),
+            [
+                root.join("b/02012024/Garage-00-100000-100500.mp4"),
+                root.join("c/01312024/Porch-00-110000-110500.mp4"),
+            ]
+            .map(|path| path.display().to_string())
+        );
+        assert_eq!(
+            db::get_scan_checkpoint(&conn, db::DEFAULT_ROOT).unwrap(),
+            None
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn unchanged_directories_are_not_reparsed() {
+        let root = scratch_dir("unchanged");
+        create_files(
+            &root,
+            &[
+                "01312024/Garage-00-080000-080500.mp4",
+                "01312024/Garage-00-090000-090500.mp4",
+                "02012024/Porch-00-100000-100500.mp4",
+            ],
+        );
+        let config = test_config(&root, "  min_age_seconds: 0");
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&conn).unwrap();
+
+        let first = scan_directory(&config, &conn, false).unwrap();
+        assert_eq!((first.files_seen, first.added), (3, 3));
+        assert_eq!(first.unchanged_directories, 0);
+
+        let second = scan_directory(&config, &conn, false).unwrap();
+        assert_eq!(second.unchanged_directories, 2);
+        assert_eq!((second.files_seen, second.unchanged), (0, 0));
+
+        // A full scan ignores the watermarks
+        let full = scan_directory(&config, &conn, true).unwrap();
+        assert_eq!((full.files_seen, full.unchanged), (3, 3));
+        assert_eq!(full.unchanged_directories, 0);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn directory_with_deferred_file_has_no_watermark() {
+        let root = scratch_dir("deferred");
+        create_files(
+            &root,
+            &[
+                "01312024/Garage-00-080000-080500.mp4",
+                "01312024/Garage-00-090000-090500.mp4",
+                "02012024/Porch-00-100000-100500.mp4",
+            ],
+        );
+        // Only the first file of 01312024 is recent enough to still be being written
+        let old = SystemTime::now() - Duration::from_secs(7200);
+        for path in [
+            "01312024/Garage-00-090000-090500.mp4",
+            "02012024/Porch-00-100000-100500.mp4",
+        ] {
+            let file = fs::File::options()
+                .write(true)
+                .open(root.join(path))
+                .unwrap();
+            file.set_modified(old).unwrap();
+        }
+        // One file per batch, so the deferral and the directory's completion are committed apart
+        let config = test_config(&root, "  min_age_seconds: 3600\n  batch_size: 1");
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&conn).unwrap();
+
+        let report = scan_directory(&config, &conn, false).unwrap();
+        assert_eq!(report.added, 2);
+        assert_eq!(
+            report.deferred,
+            [root
+                .join("01312024/Garage-00-080000-080500.mp4")
+                .display()
+                .to_string()]
+        );
+        let watermarks = db::get_directory_watermarks(&conn).unwrap();
+        let mut directories: Vec<&String> = watermarks.keys().collect();
+        directories.sort();
+        assert_eq!(directories, [&root.join("02012024").display().to_string()]);
+        fs::remove_dir_all(&root).unwrap();
+    }
 }
@@ -0,0 +1,97 @@
+use crate::cli::{IoPriority, ScanConfig};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::io;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Limits how hard a scan works the disks, so that cameras writing to the same disks are not
+/// starved. Files are parsed on a dedicated thread pool of `max_parallelism` threads running at
+/// the configured I/O priority, starting no more than `max_files_per_second`.
+pub struct Throttle {
+    pool: ThreadPool,
+    /// Minimum time between two files; `None` when unlimited.
+    interval: Option<Duration>,
+    /// Earliest time the next file may start.
+    next: Mutex<Instant>,
+}
+
+impl Throttle {
+    pub fn new(scan: &ScanConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let priority = scan.io_priority;
+        let pool = ThreadPoolBuilder::new()
+            // Zero leaves the choice to rayon, one thread per CPU
+            .num_threads(scan.max_parallelism.unwrap_or(0))
+            .thread_name(|index| format!("reopal-scan-{}", index))
+            .start_handler(move |index| {
+                if let Err(e) = set_io_priority(priority) {
+                    // Every thread fails alike, so report it once
+                    if index == 0 {
+                        eprintln!("Failed to set I/O priority: {}", e);
+                    }
+                }
+            })
+            .build()?;
+
+        Ok(Self {
+            pool,
+            interval: scan
+                .max_files_per_second
+                .filter(|rate| *rate > 0)
+                .map(|rate| Duration::from_secs(1) / rate),
+            next: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// Runs `op` on the throttle's thread pool; parallel iterators inside it use that pool.
+    pub fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> R {
+        self.pool.install(op)
+    }
+
+    /// Blocks until the next file may be read under the rate limit.
+    pub fn wait(&self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let slot = {
+            let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+            let slot = (*next).max(Instant::now());
+            *next = slot + interval;
+            slot
+        };
+        thread::sleep(slot.saturating_duration_since(Instant::now()));
+    }
+}
+
+/// Sets the I/O scheduling class of the calling thread. Threads it spawns afterwards inherit it.
+#[cfg(target_os = "linux")]
+pub fn set_io_priority(priority: IoPriority) -> io::Result<()> {
+    // From linux/ioprio.h
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    const IOPRIO_CLASS_BE: libc::c_int = 2;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+
+    let value = match priority {
+        IoPriority::Normal => return Ok(()),
+        IoPriority::Low => (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | 7,
+        IoPriority::Idle => IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+    };
+    // I/O priorities are per thread, and a pid of 0 means the calling one
+    let result = unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, value) };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_io_priority(priority: IoPriority) -> io::Result<()> {
+    match priority {
+        IoPriority::Normal => Ok(()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "I/O priorities are only supported on Linux",
+        )),
+    }
+}
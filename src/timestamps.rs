@@ -0,0 +1,241 @@
+use crate::cli::{Config, DstPolicy};
+use crate::models::{TimeFlag, TimestampSource};
+use crate::mp4::Mp4Info;
+use crate::naming::ParsedName;
+use chrono::{DateTime, Duration, FixedOffset, LocalResult, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::fs::Metadata;
+use std::time::SystemTime;
+
+/// Recording times and the source they were derived from.
+#[derive(Debug, Clone)]
+pub struct RecordingTimes {
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub time_flag: Option<TimeFlag>,
+    pub source: TimestampSource,
+}
+
+/// Derives recording times from the first configured timestamp source that yields a result.
+pub fn resolve_times(
+    metadata: &Metadata,
+    mp4_info: Option<&Mp4Info>,
+    parsed: &ParsedName,
+    config: &Config,
+) -> Option<RecordingTimes> {
+    let timezone = config.timezone_for(&parsed.camera_name);
+    let policy = config.scan.dst_policy;
+
+    // Prefer the duration in the file name, then the one in the movie header
+    let duration = match parsed.end {
+        Some(end) if end >= parsed.start => Some((end - parsed.start).num_seconds()),
+        // Handle case where end time is past midnight (next day)
+        Some(end) => Some((end - parsed.start).num_seconds() + 24 * 3600),
+        None => None,
+    };
+    let duration = || {
+        let seconds = duration
+            .or_else(|| mp4_info?.duration_seconds().map(|s| s.round() as i64))
+            .unwrap_or(0);
+        Duration::seconds(seconds)
+    };
+
+    config
+        .scan
+        .timestamp_sources
+        .iter()
+        .find_map(|&source| match source {
+            TimestampSource::Filename => {
+                let naive = parsed.date.and_time(parsed.start);
+                let (start_time, time_flag) =
+                    resolve_local_time(&naive, timezone, policy, parsed.offset)?;
+                Some(RecordingTimes {
+                    start_time,
+                    end_time: start_time + duration(),
+                    time_flag,
+                    source,
+                })
+            }
+            TimestampSource::BirthTime => from_file_time(
+                metadata.created().ok()?,
+                duration(),
+                timezone,
+                policy,
+                parsed,
+                source,
+            ),
+            TimestampSource::Mtime => from_file_time(
+                metadata.modified().ok()?,
+                duration(),
+                timezone,
+                policy,
+                parsed,
+                source,
+            ),
+            TimestampSource::Mp4Header => {
+                let start_time = mp4_info?.creation_time?;
+                Some(RecordingTimes {
+                    start_time,
+                    end_time: start_time + duration(),
+                    time_flag: None,
+                    source,
+                })
+            }
+        })
+}
+
+/// Uses a file timestamp as the end of the recording. Cameras stamp files with their local
+/// wall-clock time, so the timestamp is reinterpreted in the camera's timezone.
+fn from_file_time(
+    file_time: SystemTime,
+    duration: Duration,
+    timezone: Tz,
+    policy: DstPolicy,
+    parsed: &ParsedName,
+    source: TimestampSource,
+) -> Option<RecordingTimes> {
+    let utc_time: DateTime<Utc> = file_time.into();
+    let naive_time = utc_time.naive_utc();
+    let (end_time, time_flag) = resolve_local_time(&naive_time, timezone, policy, parsed.offset)?;
+
+    Some(RecordingTimes {
+        start_time: end_time - duration,
+        end_time,
+        time_flag,
+        source,
+    })
+}
+
+/// Converts a local wall-clock time to UTC, applying the DST policy when the time is
+/// ambiguous or falls in a gap. The returned flag records which case applied.
+pub fn resolve_local_time(
+    naive: &NaiveDateTime,
+    timezone: Tz,
+    policy: DstPolicy,
+    filename_offset: Option<FixedOffset>,
+) -> Option<(DateTime<Utc>, Option<TimeFlag>)> {
+    let (first, second, flag) = match timezone.from_local_datetime(naive) {
+        LocalResult::Single(time) => return Some((time.with_timezone(&Utc), None)),
+        LocalResult::Ambiguous(first, second) => (
+            first.with_timezone(&Utc),
+            second.with_timezone(&Utc),
+            TimeFlag::Ambiguous,
+        ),
+        LocalResult::None => {
+            // Interpret the time with the offsets in effect on either side of the gap
+            let before = offset_near(timezone, *naive - Duration::hours(3))?;
+            let after = offset_near(timezone, *naive + Duration::hours(3))?;
+            (
+                before
+                    .from_local_datetime(naive)
+                    .single()?
+                    .with_timezone(&Utc),
+                after
+                    .from_local_datetime(naive)
+                    .single()?
+                    .with_timezone(&Utc),
+                TimeFlag::Nonexistent,
+            )
+        }
+    };
+
+    let resolved = match (policy, filename_offset) {
+        (DstPolicy::UseFilenameOffset, Some(offset)) => offset
+            .from_local_datetime(naive)
+            .single()?
+            .with_timezone(&Utc),
+        (DstPolicy::Latest, _) => first.max(second),
+        _ => first.min(second),
+    };
+    Some((resolved, Some(flag)))
+}
+
+fn offset_near(timezone: Tz, naive: NaiveDateTime) -> Option<FixedOffset> {
+    timezone
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|time| time.offset().fix())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Europe::Berlin;
+
+    fn local(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        local(value).and_utc()
+    }
+
+    fn resolve(
+        naive: &str,
+        policy: DstPolicy,
+        offset: Option<i32>,
+    ) -> Option<(DateTime<Utc>, Option<TimeFlag>)> {
+        let offset = offset.map(|hours| FixedOffset::east_opt(hours * 3600).unwrap());
+        resolve_local_time(&local(naive), Berlin, policy, offset)
+    }
+
+    #[test]
+    fn unambiguous_time_is_not_flagged() {
+        for policy in [
+            DstPolicy::Earliest,
+            DstPolicy::Latest,
+            DstPolicy::UseFilenameOffset,
+        ] {
+            assert_eq!(
+                resolve("2024-07-01 12:00:00", policy, Some(1)),
+                Some((utc("2024-07-01 10:00:00"), None))
+            );
+        }
+    }
+
+    #[test]
+    fn fall_back_hour() {
+        // 02:30 occurs at both +02:00 and +01:00 on 27 October 2024
+        let ambiguous = "2024-10-27 02:30:00";
+        let flag = Some(TimeFlag::Ambiguous);
+        let cases = [
+            (DstPolicy::Earliest, None, "2024-10-27 00:30:00"),
+            (DstPolicy::Latest, None, "2024-10-27 01:30:00"),
+            (DstPolicy::UseFilenameOffset, Some(1), "2024-10-27 01:30:00"),
+            (DstPolicy::UseFilenameOffset, Some(2), "2024-10-27 00:30:00"),
+            (DstPolicy::UseFilenameOffset, None, "2024-10-27 00:30:00"),
+        ];
+        for (policy, offset, expected) in cases {
+            assert_eq!(
+                resolve(ambiguous, policy, offset),
+                Some((utc(expected), flag)),
+                "{:?} {:?}",
+                policy,
+                offset
+            );
+        }
+    }
+
+    #[test]
+    fn spring_forward_hour() {
+        // Clocks jump from 02:00 to 03:00 on 31 March 2024, so 02:30 never happens; it reads
+        // as 01:30 UTC at the old offset and 00:30 UTC at the new one
+        let nonexistent = "2024-03-31 02:30:00";
+        let flag = Some(TimeFlag::Nonexistent);
+        let cases = [
+            (DstPolicy::Earliest, None, "2024-03-31 00:30:00"),
+            (DstPolicy::Latest, None, "2024-03-31 01:30:00"),
+            (DstPolicy::UseFilenameOffset, Some(1), "2024-03-31 01:30:00"),
+            (DstPolicy::UseFilenameOffset, None, "2024-03-31 00:30:00"),
+        ];
+        for (policy, offset, expected) in cases {
+            assert_eq!(
+                resolve(nonexistent, policy, offset),
+                Some((utc(expected), flag)),
+                "{:?} {:?}",
+                policy,
+                offset
+            );
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use crate::cli::ScanConfig;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Which parts of an archive root are walked, from the `scan` settings. Imports, reconcile,
+/// ingest and the watcher all apply the same rules.
+#[derive(Debug)]
+pub struct WalkRules {
+    exclude: GlobSet,
+    /// `None` when every file is included.
+    include: Option<GlobSet>,
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+}
+
+impl WalkRules {
+    pub fn new(scan: &ScanConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let include = if scan.include.is_empty() {
+            None
+        } else {
+            Some(build_glob_set(&scan.include)?)
+        };
+        Ok(Self {
+            exclude: build_glob_set(&scan.exclude)?,
+            include,
+            max_depth: scan.max_depth,
+            follow_symlinks: scan.follow_symlinks,
+        })
+    }
+
+    /// Starts a walk of `root` with the depth limit and symlink policy applied. Entries still
+    /// need filtering with [`WalkRules::allows`].
+    pub fn walk_dir(&self, root: &Path) -> WalkDir {
+        let walk = WalkDir::new(root).follow_links(self.follow_symlinks);
+        match self.max_depth {
+            Some(depth) => walk.max_depth(depth),
+            None => walk,
+        }
+    }
+
+    /// Whether `path`, inside `root`, is walked. Directories are only checked against the
+    /// exclude patterns and depth limit; files must also match an include pattern.
+    pub fn allows(&self, root: &Path, path: &Path, is_dir: bool) -> bool {
+        let Ok(relative) = path.strip_prefix(root) else {
+            return true;
+        };
+        if relative.as_os_str().is_empty() {
+            return true;
+        }
+        if self
+            .max_depth
+            .is_some_and(|depth| relative.components().count() > depth)
+        {
+            return false;
+        }
+        // Paths reported by the watcher are not pruned like a walk, so check every ancestor
+        if relative
+            .ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| self.exclude.is_match(ancestor))
+        {
+            return false;
+        }
+        is_dir
+            || self
+                .include
+                .as_ref()
+                .is_none_or(|include| include.is_match(relative))
+    }
+
+    /// Lists the MP4 files under `directory`, which is `root` or a directory inside it.
+    pub fn find_video_files(&self, root: &Path, directory: &Path) -> Vec<PathBuf> {
+        // The depth limit counts from the root, so it is left to `allows`
+        WalkDir::new(directory)
+            .follow_links(self.follow_symlinks)
+            .into_iter()
+            .filter_entry(|entry| self.allows(root, entry.path(), entry.file_type().is_dir()))
+            .filter_map(|e| e.ok())
+            .map(|e| e.into_path())
+            .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "mp4"))
+            .collect()
+    }
+}
+
+/// Describes an error from a walk, naming symlink loops as such.
+pub fn describe_error(error: &walkdir::Error) -> String {
+    match (error.path(), error.loop_ancestor()) {
+        (Some(path), Some(ancestor)) => format!(
+            "Skipped symlink loop: {} leads back to {}",
+            path.display(),
+            ancestor.display()
+        ),
+        _ => format!("Walk error: {}", error),
+    }
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, Box<dyn std::error::Error>> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| format!("Invalid scan pattern '{}': {}", pattern, e))?;
+        builder.add(glob);
+    }
+    Ok(builder.build()?)
+}
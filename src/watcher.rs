@@ -0,0 +1,159 @@
+use crate::cli::Config;
+use crate::db;
+use crate::scanner::{self, Indexer, ParsedFile, Root, ScanReport};
+use crate::walk::WalkRules;
+use notify::event::{AccessKind, AccessMode, CreateKind, EventKind, ModifyKind, RenameMode};
+use notify::{Event, RecursiveMode, Watcher};
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often files that are still being written are checked for having gone quiet.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Watches every archive root and keeps the index current as recordings are added and
+/// removed. Runs until the watcher fails.
+pub fn watch(config: &Config, db: &Mutex<Connection>) -> Result<(), Box<dyn std::error::Error>> {
+    let roots = scanner::load_roots(config)?;
+    let rules = WalkRules::new(&config.scan)?;
+    let indexer = Indexer::new(&roots, config)?;
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    for root in &roots {
+        watcher.watch(&root.path, RecursiveMode::Recursive)?;
+        println!("Watching {} for new recordings...", root.path.display());
+    }
+
+    let quiet = Duration::from_secs(config.scan.watch_quiet_seconds);
+    // Files still being written, with the time of their last change
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    loop {
+        let mut ready = Vec::new();
+        let mut removed = Vec::new();
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) => classify(
+                event,
+                &roots,
+                &rules,
+                &mut pending,
+                &mut ready,
+                &mut removed,
+            ),
+            Ok(Err(e)) => eprintln!("Watch error: {}", e),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Err("File watcher stopped".into()),
+        }
+
+        pending.retain(|path, changed| {
+            let settled = changed.elapsed() >= quiet;
+            if settled {
+                ready.push(path.clone());
+            }
+            !settled
+        });
+
+        if ready.is_empty() && removed.is_empty() {
+            continue;
+        }
+        // Parsing is throttled and may take a while, so only hold the writer while storing
+        let parsed = indexer.parse_files(&ready);
+        let conn = db.lock().map_err(|_| "Database lock poisoned")?;
+        if let Err(e) = apply(parsed, &removed, &indexer, &conn) {
+            eprintln!("Failed to update index: {}", e);
+        }
+    }
+}
+
+/// Sorts a filesystem event into files to index now, files still being written and removed
+/// paths. Paths the walk rules leave out are ignored, except for removals.
+fn classify(
+    event: Event,
+    roots: &[Root],
+    rules: &WalkRules,
+    pending: &mut HashMap<PathBuf, Instant>,
+    ready: &mut Vec<PathBuf>,
+    removed: &mut Vec<PathBuf>,
+) {
+    for path in event.paths {
+        let Some(root) = scanner::root_for(roots, &path) else {
+            continue;
+        };
+        let is_dir = path.is_dir();
+        let allowed = rules.allows(&root.path, &path, is_dir);
+        match event.kind {
+            // Written and closed, or moved in whole
+            EventKind::Access(AccessKind::Close(AccessMode::Write))
+            | EventKind::Modify(ModifyKind::Name(RenameMode::To))
+                if allowed =>
+            {
+                if is_dir {
+                    ready.extend(rules.find_video_files(&root.path, &path));
+                } else if is_video(&path) {
+                    pending.remove(&path);
+                    ready.push(path);
+                }
+            }
+            // Files may land in a new directory before it is watched
+            EventKind::Create(CreateKind::Folder) if allowed => {
+                for file in rules.find_video_files(&root.path, &path) {
+                    pending.insert(file, Instant::now());
+                }
+            }
+            EventKind::Create(_) | EventKind::Modify(ModifyKind::Data(_))
+                if allowed && is_video(&path) =>
+            {
+                pending.insert(path, Instant::now());
+            }
+            EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                pending.retain(|file, _| !file.starts_with(&path));
+                removed.push(path);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Stores parsed settled files and marks removed ones, and any videos under removed
+/// directories, as deleted.
+fn apply(
+    parsed: Vec<ParsedFile>,
+    removed: &[PathBuf],
+    indexer: &Indexer,
+    conn: &Connection,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut report = ScanReport::default();
+    let tx = db::begin_write(conn)?;
+    indexer.store_files(parsed, &tx, &mut report)?;
+
+    let mut deleted = 0;
+    for path in removed {
+        let path = path.to_string_lossy();
+        deleted += db::mark_as_deleted(&tx, &path)?;
+        deleted += db::mark_directory_deleted(&tx, &path)?;
+    }
+    tx.commit()?;
+
+    for skipped in &report.skipped {
+        println!("Skipped {}: {}", skipped.path, skipped.reason);
+    }
+    for quarantined in &report.quarantined {
+        println!("Quarantined: {} -> {}", quarantined.from, quarantined.to);
+    }
+    for error in &report.errors {
+        eprintln!("{}", error);
+    }
+    if report.added + report.updated + report.resurrected + deleted > 0 {
+        println!(
+            "Index updated: {} added, {} updated, {} resurrected, {} deleted",
+            report.added, report.updated, report.resurrected, deleted
+        );
+    }
+    Ok(())
+}
+
+fn is_video(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "mp4")
+}
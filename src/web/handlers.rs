@@ -4,13 +4,18 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
-use chrono::{DateTime, NaiveDate, Utc};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
 use std::path::PathBuf;
 
+use crate::cli::Config;
+use crate::db::{self, HealthFilter, TimeBound, VideoFilter, VideoSort};
+use crate::models::{Trigger, VideoHealth, VideoRecording};
+use crate::scanner::ScanReport;
 use crate::web::AppState;
 
 #[derive(Deserialize)]
@@ -18,26 +23,60 @@ pub struct VideoQuery {
     pub page: Option<u32>,
     pub limit: Option<u32>,
     pub camera: Option<String>,
+    /// Inclusive lower bound: a date (`YYYY-MM-DD`) or a datetime, either RFC 3339 or local
+    /// `YYYY-MM-DDTHH:MM[:SS]`.
     pub date_from: Option<String>,
+    /// Exclusive upper bound, in the same formats as `date_from`.
     pub date_to: Option<String>,
+    /// `ok`, `truncated`, `unreadable`, or `bad` for anything but `ok`.
+    pub health: Option<String>,
+    /// `timer`, `motion`, `person` or `vehicle`; several separated by commas match any of them.
+    pub trigger: Option<String>,
+    /// File size bounds in bytes, inclusive.
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// Duration bounds in seconds, inclusive.
+    pub min_duration: Option<f64>,
+    pub max_duration: Option<f64>,
+    pub deleted: Option<bool>,
+    /// `newest` (the default) or `oldest` first.
+    pub sort: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct VideoResponse {
-    pub id: String,
+    pub id: i64,
     pub camera_name: String,
     pub date: String,
     pub start_time: String,
     pub end_time: String,
     pub file_size: u64,
-    pub file_path: String,
+    pub file_name: String,
     pub deleted: bool,
+    pub health: String,
+    pub channel: Option<u8>,
+    pub stream: Option<String>,
+    pub trigger: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct VideoDetailResponse {
+    #[serde(flatten)]
+    pub video: VideoResponse,
+    /// Camera name as found in the file name, before aliases were applied.
+    pub raw_camera_name: String,
+    pub duration: Option<f64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub codec: Option<String>,
+    pub frame_rate: Option<f64>,
+    pub bitrate: Option<u64>,
 }
 
 #[derive(Serialize)]
 pub struct VideosListResponse {
     pub videos: Vec<VideoResponse>,
-    pub total: u32,
+    pub total: u64,
     pub page: u32,
     pub limit: u32,
 }
@@ -45,10 +84,18 @@ pub struct VideosListResponse {
 #[derive(Serialize)]
 pub struct CameraResponse {
     pub name: String,
-    pub video_count: u32,
+    pub video_count: u64,
+    /// Start of the latest recording as local `YYYY-MM-DDTHH:MM:SS` in the camera's timezone.
     pub last_recording: Option<String>,
 }
 
+#[derive(Serialize)]
+pub struct ImportResponse {
+    pub status: String,
+    pub message: String,
+    pub report: Option<ScanReport>,
+}
+
 #[derive(Deserialize)]
 pub struct SearchRequest {
     pub query: String,
@@ -57,209 +104,265 @@ pub struct SearchRequest {
     pub date_to: Option<String>,
 }
 
-/// Helper function to format DateTime to HHMMSS string
-fn format_time_to_string(datetime: &DateTime<Utc>) -> String {
-    datetime.format("%H%M%S").to_string()
-}
-
-/// Helper function to convert ISO date format (YYYY-MM-DD) to database format (MMDDYYYY)
-fn convert_iso_date_to_db_format(iso_date: &str) -> Option<String> {
-    let date = NaiveDate::parse_from_str(iso_date, "%Y-%m-%d").ok()?;
-    Some(date.format("%m%d%Y").to_string())
+/// Helper function to format DateTime to HHMMSS string in the camera's timezone
+fn format_time_to_string(datetime: &DateTime<Utc>, timezone: Tz) -> String {
+    datetime
+        .with_timezone(&timezone)
+        .format("%H%M%S")
+        .to_string()
 }
 
-/// List all videos with pagination and filtering
-pub async fn list_videos(
-    State(state): State<AppState>,
-    Query(params): Query<VideoQuery>,
-) -> Result<Json<VideosListResponse>, StatusCode> {
-    let page = params.page.unwrap_or(1);
-    let limit = params.limit.unwrap_or(20);
-    let offset = (page - 1) * limit;
+/// Parses one bound of a date range: a date (`YYYY-MM-DD`), compared with the recording date,
+/// or a datetime, either RFC 3339 or local `YYYY-MM-DDTHH:MM[:SS]` in `timezone`, compared with
+/// the start time. Returns `None` for values that are neither.
+fn parse_time_bound(value: &str, timezone: Tz) -> Option<TimeBound> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Some(TimeBound::Date(date));
+    }
 
-    let db = state
-        .db
-        .lock()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let instant = match DateTime::parse_from_rfc3339(value) {
+        Ok(datetime) => datetime.with_timezone(&Utc),
+        Err(_) => {
+            let local = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+                .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M"))
+                .ok()?;
+            timezone
+                .from_local_datetime(&local)
+                .earliest()?
+                .with_timezone(&Utc)
+        }
+    };
+    Some(TimeBound::Instant(instant))
+}
 
-    // Build the WHERE clause for both count and data queries
-    let mut where_clause = "WHERE 1=1".to_string();
-    let mut conditions = Vec::new();
+/// Builds the filter shared by listing and search: a camera and a date range. Local datetimes
+/// in the range are in the camera's timezone when filtering by camera.
+fn base_filter(
+    camera: Option<String>,
+    date_from: Option<&str>,
+    date_to: Option<&str>,
+    config: &Config,
+) -> Result<VideoFilter, StatusCode> {
+    let timezone = camera
+        .as_deref()
+        .map_or(config.timezone, |camera| config.timezone_for(camera));
+    let bound = |value: Option<&str>| {
+        value
+            .map(|value| parse_time_bound(value, timezone).ok_or(StatusCode::BAD_REQUEST))
+            .transpose()
+    };
 
-    if let Some(camera) = &params.camera {
-        where_clause.push_str(" AND camera_name = ?");
-        conditions.push(camera.clone());
-    }
+    Ok(VideoFilter {
+        from: bound(date_from)?,
+        to: bound(date_to)?,
+        cameras: camera.into_iter().collect(),
+        ..Default::default()
+    })
+}
 
-    if let Some(date_from) = &params.date_from {
-        if let Some(converted_date) = convert_iso_date_to_db_format(date_from) {
-            where_clause.push_str(" AND date >= ?");
-            conditions.push(converted_date);
+impl VideoQuery {
+    fn to_filter(&self, config: &Config) -> Result<VideoFilter, StatusCode> {
+        let mut filter = base_filter(
+            self.camera.clone(),
+            self.date_from.as_deref(),
+            self.date_to.as_deref(),
+            config,
+        )?;
+        filter.health = match self.health.as_deref() {
+            Some("bad") => Some(HealthFilter::Unhealthy),
+            Some(health) => Some(HealthFilter::Is(
+                VideoHealth::parse(health).ok_or(StatusCode::BAD_REQUEST)?,
+            )),
+            None => None,
+        };
+        if let Some(triggers) = &self.trigger {
+            filter.triggers = triggers
+                .split(',')
+                .map(|trigger| Trigger::parse(trigger.trim()).ok_or(StatusCode::BAD_REQUEST))
+                .collect::<Result<_, _>>()?;
         }
+        filter.sort = match self.sort.as_deref() {
+            None | Some("newest") => VideoSort::Newest,
+            Some("oldest") => VideoSort::Oldest,
+            Some(_) => return Err(StatusCode::BAD_REQUEST),
+        };
+        filter.min_size = self.min_size;
+        filter.max_size = self.max_size;
+        filter.min_duration = self.min_duration;
+        filter.max_duration = self.max_duration;
+        filter.deleted = self.deleted;
+        Ok(filter)
     }
+}
 
-    if let Some(date_to) = &params.date_to {
-        if let Some(converted_date) = convert_iso_date_to_db_format(date_to) {
-            where_clause.push_str(" AND date < ?");
-            conditions.push(converted_date);
-        }
+/// Maps an indexed video to its API representation, with times in the camera's timezone.
+fn video_response(record: &VideoRecording, config: &Config) -> VideoResponse {
+    let timezone = config.timezone_for(&record.camera_name);
+    VideoResponse {
+        id: record.id.unwrap_or_default(),
+        camera_name: record.camera_name.clone(),
+        date: record.date.clone(),
+        start_time: format_time_to_string(&record.start_time, timezone),
+        end_time: format_time_to_string(&record.end_time, timezone),
+        file_size: record.file_size,
+        file_name: file_name(&record.file_path),
+        deleted: record.deleted,
+        health: record.health.as_str().to_string(),
+        channel: record.flags.channel,
+        stream: record
+            .flags
+            .stream
+            .map(|stream| stream.as_str().to_string()),
+        trigger: record
+            .flags
+            .trigger
+            .map(|trigger| trigger.as_str().to_string()),
     }
+}
 
-    // First, get the total count
-    let count_query = format!("SELECT COUNT(*) FROM videos {}", where_clause);
-    let mut count_stmt = db
-        .prepare(&count_query)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let total: u32 = count_stmt
-        .query_row(rusqlite::params_from_iter(conditions.iter()), |row| {
-            row.get::<_, u32>(0)
-        })
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    // Build the main query
-    let mut query = format!("SELECT file_path, camera_name, date, start_time, end_time, file_size, deleted FROM videos {}", where_clause);
-    query.push_str(" ORDER BY date DESC, start_time DESC");
-
-    // Add pagination if limit is reasonable (not trying to get all records)
-    if limit > 0 && limit <= 10000 {
-        query.push_str(" LIMIT ? OFFSET ?");
-        conditions.push(limit.to_string());
-        conditions.push(offset.to_string());
-    }
+/// List all videos with pagination and filtering
+pub async fn list_videos(
+    State(state): State<AppState>,
+    Query(params): Query<VideoQuery>,
+) -> Result<Json<VideosListResponse>, StatusCode> {
+    let page = params.page.unwrap_or(1);
+    let limit = params.limit.unwrap_or(20);
 
-    let mut stmt = db
-        .prepare(&query)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let video_iter = stmt
-        .query_map(rusqlite::params_from_iter(conditions.iter()), |row| {
-            let start_time: DateTime<Utc> = row.get(3)?;
-            let end_time: DateTime<Utc> = row.get(4)?;
-
-            Ok(VideoResponse {
-                id: generate_video_id(row.get::<_, String>(0)?),
-                camera_name: row.get(1)?,
-                date: row.get(2)?,
-                start_time: format_time_to_string(&start_time),
-                end_time: format_time_to_string(&end_time),
-                file_size: row.get(5)?,
-                file_path: row.get(0)?,
-                deleted: row.get(6)?,
+    state
+        .read(move |db, config| {
+            let mut filter = params.to_filter(config)?;
+            let total =
+                db::count_videos(db, &filter).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+            // Paginate if limit is reasonable (not trying to get all records)
+            if limit > 0 && limit <= 10000 {
+                filter.limit = Some(limit);
+                filter.offset = page
+                    .saturating_sub(1)
+                    .checked_mul(limit)
+                    .ok_or(StatusCode::BAD_REQUEST)?;
+            }
+            let videos = db::query_videos(db, &filter)
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .iter()
+                .map(|record| video_response(record, config))
+                .collect();
+
+            Ok(VideosListResponse {
+                videos,
+                total,
+                page,
+                limit,
             })
         })
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let videos: Result<Vec<VideoResponse>, _> = video_iter.collect();
-    let videos = videos.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    Ok(Json(VideosListResponse {
-        videos,
-        total,
-        page,
-        limit,
-    }))
+        .await
+        .map(Json)
 }
 
-/// Get specific video metadata
+/// Get specific video metadata, including properties read from the MP4 container
 pub async fn get_video(
     State(state): State<AppState>,
-    Path(id): Path<String>,
-) -> Result<Json<VideoResponse>, StatusCode> {
-    let db = state
-        .db
-        .lock()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let mut stmt = db
-        .prepare("SELECT file_path, camera_name, date, start_time, end_time, file_size, deleted FROM videos WHERE file_path = ?")
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let file_path = decode_video_id(id);
-    let video = stmt
-        .query_row([&file_path], |row| {
-            let start_time: DateTime<Utc> = row.get(3)?;
-            let end_time: DateTime<Utc> = row.get(4)?;
-
-            Ok(VideoResponse {
-                id: generate_video_id(row.get::<_, String>(0)?),
-                camera_name: row.get(1)?,
-                date: row.get(2)?,
-                start_time: format_time_to_string(&start_time),
-                end_time: format_time_to_string(&end_time),
-                file_size: row.get(5)?,
-                file_path: row.get(0)?,
-                deleted: row.get(6)?,
+    Path(id): Path<i64>,
+) -> Result<Json<VideoDetailResponse>, StatusCode> {
+    state
+        .read(move |db, config| {
+            let record = db::get_recording(db, id).map_err(|_| StatusCode::NOT_FOUND)?;
+
+            Ok(VideoDetailResponse {
+                video: video_response(&record, config),
+                raw_camera_name: record.raw_camera_name,
+                duration: record.media.duration,
+                width: record.media.width,
+                height: record.media.height,
+                codec: record.media.codec,
+                frame_rate: record.media.frame_rate,
+                bitrate: record.media.bitrate,
             })
         })
-        .map_err(|_| StatusCode::NOT_FOUND)?;
-
-    Ok(Json(video))
+        .await
+        .map(Json)
 }
 
 /// Stream video file with range support
 pub async fn stream_video(
     State(state): State<AppState>,
-    Path(id): Path<String>,
+    Path(id): Path<i64>,
     headers: HeaderMap,
 ) -> Result<Response, StatusCode> {
-    let file_path = decode_video_id(id);
-    let full_path = PathBuf::from(&state.config.directory).join(&file_path);
-
-    // Check if file exists
-    if !full_path.exists() {
+    let record = state
+        .read(move |db, _| db::get_recording(db, id).map_err(|_| StatusCode::NOT_FOUND))
+        .await?;
+    if record.deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let full_path = PathBuf::from(record.file_path);
+
+    // Only serve indexed files that exist under one of the archive roots
+    let in_root = state
+        .config
+        .roots()
+        .iter()
+        .any(|root| full_path.starts_with(&root.path));
+    if !in_root || !full_path.exists() {
         return Err(StatusCode::NOT_FOUND);
     }
 
-    let mut file = File::open(&full_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let file_size = file
-        .metadata()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .len();
-
-    // Handle range requests
-    if let Some(range_header) = headers.get("range") {
-        let range_str = range_header.to_str().map_err(|_| StatusCode::BAD_REQUEST)?;
-
-        if let Some(range) = parse_range_header(range_str, file_size) {
-            let content_length = range.1 - range.0 + 1;
-            let mut buffer = vec![0u8; content_length as usize];
-
-            file.seek(SeekFrom::Start(range.0))
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-            file.read_exact(&mut buffer)
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-            return Ok((
-                StatusCode::PARTIAL_CONTENT,
-                [
-                    (header::CONTENT_TYPE, "video/mp4"),
-                    (header::CONTENT_LENGTH, &content_length.to_string()),
-                    (
-                        header::CONTENT_RANGE,
-                        &format!("bytes {}-{}/{}", range.0, range.1, file_size),
-                    ),
-                    (header::ACCEPT_RANGES, "bytes"),
-                ],
-                buffer,
-            )
-                .into_response());
+    // Reading the file blocks, so keep it off the async executor
+    tokio::task::spawn_blocking(move || {
+        let mut file = File::open(&full_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let file_size = file
+            .metadata()
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .len();
+
+        // Handle range requests
+        if let Some(range_header) = headers.get("range") {
+            let range_str = range_header.to_str().map_err(|_| StatusCode::BAD_REQUEST)?;
+
+            if let Some(range) = parse_range_header(range_str, file_size) {
+                let content_length = range.1 - range.0 + 1;
+                let mut buffer = vec![0u8; content_length as usize];
+
+                file.seek(SeekFrom::Start(range.0))
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                file.read_exact(&mut buffer)
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                return Ok((
+                    StatusCode::PARTIAL_CONTENT,
+                    [
+                        (header::CONTENT_TYPE, "video/mp4"),
+                        (header::CONTENT_LENGTH, &content_length.to_string()),
+                        (
+                            header::CONTENT_RANGE,
+                            &format!("bytes {}-{}/{}", range.0, range.1, file_size),
+                        ),
+                        (header::ACCEPT_RANGES, "bytes"),
+                    ],
+                    buffer,
+                )
+                    .into_response());
+            }
         }
-    }
 
-    // Full file response
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    Ok((
-        StatusCode::OK,
-        [
-            (header::CONTENT_TYPE, "video/mp4"),
-            (header::CONTENT_LENGTH, &file_size.to_string()),
-            (header::ACCEPT_RANGES, "bytes"),
-        ],
-        buffer,
-    )
-        .into_response())
+        // Full file response
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        Ok((
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, "video/mp4"),
+                (header::CONTENT_LENGTH, &file_size.to_string()),
+                (header::ACCEPT_RANGES, "bytes"),
+            ],
+            buffer,
+        )
+            .into_response())
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
 }
 
 /// Search videos by query
@@ -267,92 +370,61 @@ pub async fn search_videos(
     State(state): State<AppState>,
     Json(search_req): Json<SearchRequest>,
 ) -> Result<Json<Vec<VideoResponse>>, StatusCode> {
-    let db = state
-        .db
-        .lock()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let mut query = "SELECT file_path, camera_name, date, start_time, end_time, file_size, deleted FROM videos WHERE (camera_name LIKE ? OR file_path LIKE ?)".to_string();
-    let mut conditions = vec![
-        format!("%{}%", search_req.query),
-        format!("%{}%", search_req.query),
-    ];
-
-    if let Some(camera) = &search_req.camera {
-        query.push_str(" AND camera_name = ?");
-        conditions.push(camera.clone());
-    }
-
-    if let Some(date_from) = &search_req.date_from {
-        if let Some(converted_date) = convert_iso_date_to_db_format(date_from) {
-            query.push_str(" AND date >= ?");
-            conditions.push(converted_date);
-        }
-    }
-
-    if let Some(date_to) = &search_req.date_to {
-        if let Some(converted_date) = convert_iso_date_to_db_format(date_to) {
-            query.push_str(" AND date < ?");
-            conditions.push(converted_date);
-        }
-    }
-
-    query.push_str(" ORDER BY date DESC, start_time DESC");
-
-    let mut stmt = db
-        .prepare(&query)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let video_iter = stmt
-        .query_map(rusqlite::params_from_iter(conditions.iter()), |row| {
-            let start_time: DateTime<Utc> = row.get(3)?;
-            let end_time: DateTime<Utc> = row.get(4)?;
-
-            Ok(VideoResponse {
-                id: generate_video_id(row.get::<_, String>(0)?),
-                camera_name: row.get(1)?,
-                date: row.get(2)?,
-                start_time: format_time_to_string(&start_time),
-                end_time: format_time_to_string(&end_time),
-                file_size: row.get(5)?,
-                file_path: row.get(0)?,
-                deleted: row.get(6)?,
-            })
+    state
+        .read(move |db, config| {
+            let filter = VideoFilter {
+                text: Some(search_req.query),
+                ..base_filter(
+                    search_req.camera,
+                    search_req.date_from.as_deref(),
+                    search_req.date_to.as_deref(),
+                    config,
+                )?
+            };
+            let videos = db::query_videos(db, &filter)
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .iter()
+                .map(|record| video_response(record, config))
+                .collect();
+
+            Ok(videos)
         })
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let videos: Result<Vec<VideoResponse>, _> = video_iter.collect();
-    let videos = videos.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    Ok(Json(videos))
+        .await
+        .map(Json)
 }
 
 /// List all cameras
 pub async fn list_cameras(
     State(state): State<AppState>,
 ) -> Result<Json<Vec<CameraResponse>>, StatusCode> {
-    let db = state
-        .db
-        .lock()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let mut stmt = db
-        .prepare("SELECT camera_name, COUNT(*) as video_count, MAX(date || start_time) as last_recording FROM videos GROUP BY camera_name ORDER BY camera_name")
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let camera_iter = stmt
-        .query_map([], |row| {
-            Ok(CameraResponse {
-                name: row.get(0)?,
-                video_count: row.get(1)?,
-                last_recording: row.get(2)?,
-            })
+    state
+        .read(move |db, config| {
+            // Deleted videos are gone from the archive, so they are neither counted nor dated
+            let filter = VideoFilter {
+                deleted: Some(false),
+                ..Default::default()
+            };
+            let cameras = db::summarize_cameras(db, &filter)
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .into_iter()
+                .map(|camera| {
+                    let timezone = config.timezone_for(&camera.name);
+                    CameraResponse {
+                        video_count: camera.video_count,
+                        last_recording: camera.last_recording.map(|time| {
+                            time.with_timezone(&timezone)
+                                .format("%Y-%m-%dT%H:%M:%S")
+                                .to_string()
+                        }),
+                        name: camera.name,
+                    }
+                })
+                .collect();
+
+            Ok(cameras)
         })
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let cameras: Result<Vec<CameraResponse>, _> = camera_iter.collect();
-    let cameras = cameras.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    Ok(Json(cameras))
+        .await
+        .map(Json)
 }
 
 /// Health check endpoint
@@ -366,45 +438,40 @@ pub async fn health_check() -> Json<HashMap<String, String>> {
 /// Manual import/refresh endpoint
 pub async fn manual_import(
     State(state): State<AppState>,
-) -> Result<Json<HashMap<String, String>>, StatusCode> {
-    let db = state
-        .db
-        .lock()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    // Release the database lock before calling scanner
-    drop(db);
-
-    // Call the scanner function to import new videos
-    match crate::scanner::scan_directory(&state.config.directory, &state.db.lock().unwrap()) {
-        Ok(()) => {
-            let mut response = HashMap::new();
-            response.insert("status".to_string(), "success".to_string());
-            response.insert(
-                "message".to_string(),
-                "Video metadata refreshed successfully".to_string(),
-            );
-            Ok(Json(response))
-        }
-        Err(e) => {
-            eprintln!("Import error: {}", e);
-            let mut response = HashMap::new();
-            response.insert("status".to_string(), "error".to_string());
-            response.insert("message".to_string(), format!("Import failed: {}", e));
-            Ok(Json(response))
-        }
-    }
+) -> Result<Json<ImportResponse>, StatusCode> {
+    state
+        .write(move |db, config| {
+            // Call the scanner function to import new videos
+            match crate::scanner::scan_directory(config, db, false) {
+                Ok(report) => Ok(ImportResponse {
+                    status: "success".to_string(),
+                    message: format!(
+                        "Video metadata refreshed successfully: {} added, {} updated, {} resurrected",
+                        report.added, report.updated, report.resurrected
+                    ),
+                    report: Some(report),
+                }),
+                Err(e) => {
+                    eprintln!("Import error: {}", e);
+                    Ok(ImportResponse {
+                        status: "error".to_string(),
+                        message: format!("Import failed: {}", e),
+                        report: None,
+                    })
+                }
+            }
+        })
+        .await
+        .map(Json)
 }
 
 // Helper functions
-fn generate_video_id(file_path: String) -> String {
-    use base64::prelude::*;
-    BASE64_STANDARD.encode(file_path.as_bytes())
-}
-
-fn decode_video_id(id: String) -> String {
-    use base64::prelude::*;
-    String::from_utf8(BASE64_STANDARD.decode(id).unwrap_or_default()).unwrap_or_default()
+/// File name of an indexed video, shown in place of its path on the server.
+fn file_name(file_path: &str) -> String {
+    std::path::Path::new(file_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
 }
 
 fn parse_range_header(range_header: &str, file_size: u64) -> Option<(u64, u64)> {
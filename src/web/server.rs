@@ -21,7 +21,9 @@ impl WebServer {
         let listener = TcpListener::bind(addr).await?;
 
         println!("🚀 ReoPal Web Viewer starting on http://{}:{}", host, port);
-        println!("📹 Serving videos from: {}", self.state.config.directory);
+        for root in self.state.config.roots() {
+            println!("📹 Serving videos from: {} ({})", root.path, root.name);
+        }
 
         serve(listener, app).await?;
 
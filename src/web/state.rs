@@ -1,19 +1,59 @@
-use crate::cli::Config;
+use crate::cli::{Config, WebViewerConfig};
+use crate::db::{self, ReadPool};
+use axum::http::StatusCode;
 use rusqlite::Connection;
 use std::sync::{Arc, Mutex};
 
 /// Shared application state for the web server
 #[derive(Clone)]
 pub struct AppState {
+    /// The only connection that writes, shared with the watcher.
     pub db: Arc<Mutex<Connection>>,
+    pub readers: ReadPool,
     pub config: Arc<Config>,
 }
 
 impl AppState {
-    pub fn new(db: Connection, config: Config) -> Self {
-        Self {
+    pub fn new(db: Connection, config: Config) -> Result<Self, r2d2::Error> {
+        let default_config = WebViewerConfig::default();
+        let web_config = config.web_viewer.as_ref().unwrap_or(&default_config);
+        Ok(Self {
             db: Arc::new(Mutex::new(db)),
+            readers: db::open_read_pool(&config.db_path, web_config.read_connections)?,
             config: Arc::new(config),
-        }
+        })
+    }
+
+    /// Runs `query` with a connection from the read pool on the blocking thread pool, so that
+    /// slow queries don't stall other requests.
+    pub async fn read<T, F>(&self, query: F) -> Result<T, StatusCode>
+    where
+        F: FnOnce(&Connection, &Config) -> Result<T, StatusCode> + Send + 'static,
+        T: Send + 'static,
+    {
+        let readers = self.readers.clone();
+        let config = self.config.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = readers.get().map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+            query(&conn, &config)
+        })
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    }
+
+    /// Runs `update` with the writer connection on the blocking thread pool.
+    pub async fn write<T, F>(&self, update: F) -> Result<T, StatusCode>
+    where
+        F: FnOnce(&Connection, &Config) -> Result<T, StatusCode> + Send + 'static,
+        T: Send + 'static,
+    {
+        let db = self.db.clone();
+        let config = self.config.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = db.lock().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            update(&conn, &config)
+        })
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
     }
 }
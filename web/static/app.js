@@ -363,7 +363,8 @@ class ReoPalApp {
                 <p><strong>Date:</strong> ${this.formatDate(video.date)}</p>
                 <p><strong>Time:</strong> ${this.formatTime(video.start_time)} - ${this.formatTime(video.end_time)}</p>
                 <p><strong>Size:</strong> ${this.formatFileSize(video.file_size)}</p>
-                <p><strong>File:</strong> ${video.file_path}</p>
+                ${video.codec ? `<p><strong>Video:</strong> ${video.codec.toUpperCase()} ${video.width}x${video.height}${video.frame_rate ? ` @ ${video.frame_rate.toFixed(1)} fps` : ''}</p>` : ''}
+                <p><strong>File:</strong> ${video.file_name}</p>
             </div>
         `;
 
@@ -491,18 +492,15 @@ class ReoPalApp {
     }
 
     formatDate(dateStr) {
-        if (!dateStr || dateStr.length !== 8) return dateStr;
-        const month = dateStr.substring(0, 2);
-        const day = dateStr.substring(2, 4);
-        const year = dateStr.substring(4, 8);
+        if (!dateStr || dateStr.length !== 10) return dateStr;
+        const [year, month, day] = dateStr.split('-');
         return `${month}/${day}/${year}`;
     }
 
     formatDateTime(dateTimeStr) {
-        if (!dateTimeStr || dateTimeStr.length !== 14) return dateTimeStr;
-        const date = dateTimeStr.substring(0, 8);
-        const time = dateTimeStr.substring(8, 14);
-        return `${this.formatDate(date)} ${this.formatTime(time)}`;
+        if (!dateTimeStr || dateTimeStr.length !== 19) return dateTimeStr;
+        const [date, time] = dateTimeStr.split('T');
+        return `${this.formatDate(date)} ${time}`;
     }
 
     formatFileSize(bytes) {